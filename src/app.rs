@@ -1,7 +1,18 @@
-use crate::config::{Config, Environment};
+use crate::component::session::SessionAnySessionStore;
+use crate::component::{cache, redis, ComponentProvider, ComponentRegister};
+use crate::config::reload::{self, ReloadConfig};
+use crate::config::{config_keys, Config, ConfigCheck, Environment};
+use crate::daemon::DaemonConfig;
 use crate::error::Result;
+use crate::http::app::ServerConfig;
+use crate::logger::{self, LogConfig};
+use crate::scheduler::{self, Tasks};
+use crate::worker::{self, Jobs};
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
 
 #[derive(Clone)]
 pub struct AppContext<T>
@@ -11,19 +22,58 @@ where
     pub app: T,
     pub config: Arc<Config>,
     pub environment: Arc<Environment>,
+    /// Components created by [`AppTrait::components`] or on first use through
+    /// [`AppContext::component`]
+    pub components: Arc<ComponentRegister>,
 }
 
 impl<T> AppContext<T>
 where
     T: AppTrait,
 {
-    pub fn new(app: T, config: Config, environment: Environment) -> Self {
+    pub fn new(
+        app: T,
+        config: Config,
+        environment: Environment,
+        components: ComponentRegister,
+    ) -> Self {
         AppContext {
             app,
             config: Arc::new(config),
             environment: Arc::new(environment),
+            components: Arc::new(components),
         }
     }
+
+    /// Receive the reloaded config, see [`ComponentRegister::subscribe_config`].
+    /// [`AppContext::config`] stays the boot config.
+    pub fn subscribe_config(&self) -> watch::Receiver<Arc<Config>> {
+        self.components.subscribe_config()
+    }
+
+    /// Get a component, creating it on first use when it was not created by
+    /// [`AppTrait::components`].
+    ///
+    /// # Errors
+    /// Return an error when the component could not be created.
+    pub async fn component<C>(&self) -> Result<C>
+    where
+        C: ComponentProvider + 'static,
+    {
+        self.components.component::<C>().await
+    }
+
+    /// Get the instance `name` of a component, see
+    /// [`ComponentRegister::component_named`].
+    ///
+    /// # Errors
+    /// Return an error when the component could not be created.
+    pub async fn component_named<C>(&self, name: &str) -> Result<C>
+    where
+        C: ComponentProvider + 'static,
+    {
+        self.components.component_named::<C>(name).await
+    }
 }
 
 impl<T> Deref for AppContext<T>
@@ -42,6 +92,31 @@ where
 pub trait AppTrait: Sized + Clone + Send + Sync + 'static {
     fn app_name() -> &'static str;
 
+    /// Create the components used by the application, e.g.
+    /// `register.component::<DbConn>()`. They are shared through
+    /// [`AppContext::components`] afterwards.
+    async fn components(_register: &ComponentRegister) -> Result<()> {
+        Ok(())
+    }
+
+    /// Register the jobs run by `panshi worker`, e.g.
+    /// `jobs.register::<SendWelcome>()`.
+    fn jobs(_jobs: &mut Jobs<Self>) {}
+
+    /// Register the periodic tasks run by `panshi scheduler`, or by `start`
+    /// when `scheduler.run_with_server` is set.
+    ///
+    /// # Errors
+    /// Return an error when a task is invalid, e.g. its cron expression.
+    fn tasks(_tasks: &mut Tasks<Self>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Check the config sections of the components used by the application,
+    /// e.g. `check.component::<DbConn>()`, run by `panshi config check`
+    /// besides the built-in sections.
+    fn check_config(_check: &mut ConfigCheck) {}
+
     async fn init(config: Config, environment: Environment) -> Result<Self>;
 }
 
@@ -49,6 +124,90 @@ pub(crate) async fn create_app<T>(config: Config, environment: Environment) -> R
 where
     T: AppTrait + 'static,
 {
+    let components = ComponentRegister::with_app_name(config.clone(), T::app_name());
+    T::components(&components).await?;
+
     let app = T::init(config.clone(), environment.clone()).await?;
-    Ok(AppContext::new(app, config, environment))
+    let ctx = AppContext::new(app, config, environment, components);
+
+    Ok(ctx)
+}
+
+/// Watch the config files when `[hot_reload]` is enabled, and apply the
+/// `[log]` section of the reloaded configs. Run by the long-running commands,
+/// the watch stops when the returned set is dropped.
+///
+/// # Errors
+/// Return an error when the `[hot_reload]` section is invalid.
+pub(crate) fn watch_config<T: AppTrait>(ctx: &AppContext<T>) -> Result<JoinSet<()>> {
+    let mut tasks = JoinSet::new();
+    let Some(reload) = ReloadConfig::from_config(&ctx.config)? else {
+        return Ok(tasks);
+    };
+
+    tasks.spawn(logger::follow(ctx.subscribe_config()));
+
+    let (config, environment) = (ctx.config.clone(), (*ctx.environment).clone());
+    let components = ctx.components.clone();
+    tasks.spawn(async move {
+        reload::watch(
+            &config,
+            environment,
+            Duration::from_millis(reload.interval),
+            |config| {
+                let check = check_config::<T>(config);
+                match check.error_count() {
+                    0 => Ok(()),
+                    _ => Err(check
+                        .results()
+                        .iter()
+                        .filter_map(|(key, error)| Some(format!("{key}: {}", error.as_ref()?)))
+                        .collect()),
+                }
+            },
+            |config| components.publish_config(config),
+        )
+        .await;
+    });
+
+    Ok(tasks)
+}
+
+/// Deserialize the built-in sections and the ones of
+/// [`AppTrait::check_config`] and build the enabled middlewares, run by
+/// `panshi config check` and before a reloaded config is accepted.
+pub fn check_config<T: AppTrait>(config: &Config) -> ConfigCheck<'_> {
+    let mut check = ConfigCheck::new(config);
+    check
+        .section::<ServerConfig>(config_keys::SERVER)
+        .optional_section::<LogConfig>(config_keys::LOG)
+        .optional_section::<DaemonConfig>(config_keys::DAEMON)
+        .optional_section::<scheduler::Config>(config_keys::SCHEDULER)
+        .optional_section::<ReloadConfig>(config_keys::HOT_RELOAD)
+        .component_instances::<redis::AnyRedisPool>()
+        .component_instances::<SessionAnySessionStore>()
+        .component_instances::<cache::Cache>()
+        .component_instances::<worker::JobQueue>();
+    #[cfg(feature = "with-db")]
+    check.component_instances::<crate::component::database::DB>();
+    // the errors of the section itself are reported above
+    if let Ok(server) = config.get::<ServerConfig>(config_keys::SERVER) {
+        for middleware in server.middlewares.middlewares() {
+            if !middleware.is_enabled() {
+                continue;
+            }
+            if let Err(err) = middleware.apply(axum::Router::new()) {
+                let key = format!("{}.middlewares.{}", config_keys::SERVER, middleware.name());
+                check.error(&key, err);
+            }
+        }
+    }
+    if let Err(err) = LogConfig::from_config(config).and_then(|log| log.env_filter()) {
+        check.error("log.filters", err);
+    }
+    if let Err(err) = scheduler::tasks::<T>() {
+        check.error("scheduler tasks", err);
+    }
+    T::check_config(&mut check);
+    check
 }
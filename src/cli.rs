@@ -1,6 +1,16 @@
-use crate::app::AppTrait;
-use crate::config::{resolve_dotenv_file, resolve_from_env, Environment, DEFAULT_ENVIRONMENT};
+use crate::app;
+use crate::config::{
+    redact, resolve_dotenv_file, resolve_from_env, Config, Environment, DEFAULT_ENVIRONMENT,
+};
+use crate::daemon::{self, DaemonConfig};
+use crate::error::{Error, Result};
+use crate::http::app::{build_routes, start, AppTrait};
+use crate::http::route::{ListRoutes, RouteInfo};
+use crate::logger::{self, LogConfig};
+use crate::scheduler::{self, Tasks};
+use crate::worker;
 use clap::{Parser, Subcommand};
+use serde::Serialize;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -16,22 +26,291 @@ struct Cli {
 
 #[derive(Subcommand)]
 pub enum Commands {
-    Start {},
+    /// Start the application server
+    Start {
+        /// Detach from the terminal and run in background, see the `[daemon]`
+        /// config section
+        #[arg(short, long)]
+        daemon: bool,
+    },
+    /// Stop the application started with `start --daemon`
+    Stop {},
+    /// Show whether the application started with `start --daemon` is running
+    Status {},
+    /// Run the background jobs until a shutdown signal is received
+    Worker {
+        /// Number of jobs run at the same time [default: `worker.concurrency`]
+        #[arg(short, long)]
+        concurrency: Option<usize>,
+
+        /// Queue to fetch jobs from, may be repeated, in priority order
+        /// [default: `worker.queues`]
+        #[arg(short, long = "queue")]
+        queues: Vec<String>,
+    },
+    /// Run the periodic tasks until a shutdown signal is received
+    Scheduler {
+        /// Print the tasks and their next run instead
+        #[arg(long)]
+        list: bool,
+    },
+    /// Check, print or query the config of the selected environment
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Print the route table without starting the server
+    Routes {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+impl Commands {
+    /// Whether the command runs until a shutdown signal is received, the
+    /// others print their output and exit
+    fn is_long_running(&self) -> bool {
+        matches!(
+            self,
+            Self::Start { .. } | Self::Worker { .. } | Self::Scheduler { list: false }
+        )
+    }
 }
 
-pub fn main<T: AppTrait>() -> crate::error::Result<()> {
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Deserialize every config section and report all the errors
+    Check {},
+    /// Print the merged config with the layer of each value, secrets
+    /// redacted
+    Print {
+        /// Print as JSON, without the layers
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the value of a key, secrets redacted, and the layer it comes
+    /// from
+    Get {
+        /// Dotted key, e.g. `server.listen`
+        key: String,
+    },
+}
+
+pub fn main<T: AppTrait>() -> Result<()> {
     let cli = Cli::parse();
 
-    let dotenv = resolve_dotenv_file();
+    resolve_dotenv_file();
     let env: Environment = cli.environment.unwrap_or_else(resolve_from_env).into();
 
-    let config = env.load_config().expect("Failed to load config");
+    logger::init_default()?;
+    let config = env.load_config()?;
+    if !cli.command.is_long_running() {
+        // the logs stay on stderr, only the filter follows `[log]`
+        if let Err(err) =
+            LogConfig::from_config(&config).and_then(|log| logger::reload_filter(&log))
+        {
+            tracing::warn!(error = %err, "invalid `[log]` section, keeping the default filter");
+        }
+    }
 
     match cli.command {
-        Commands::Start {} => {
-            println!("Starting application");
+        Commands::Start { daemon } => {
+            let daemon_config = if daemon {
+                let daemon_config = DaemonConfig::from_config(&config)?;
+                // fork before the runtime is built, threads do not survive it
+                daemon::daemonize(&daemon_config)?;
+                Some(daemon_config)
+            } else {
+                None
+            };
+
+            let _log_guard = logger::init(&LogConfig::from_config(&config)?)?;
+
+            let result = runtime()?.block_on(start::<T>(config, env));
+
+            if let Some(daemon_config) = daemon_config {
+                daemon::remove_pid_file(&daemon_config);
+            }
+            result?;
+        }
+        Commands::Worker {
+            concurrency,
+            queues,
+        } => {
+            let _log_guard = logger::init(&LogConfig::from_config(&config)?)?;
+            runtime()?.block_on(worker::start::<T>(config, env, concurrency, queues))?;
+        }
+        Commands::Scheduler { list } => {
+            if list {
+                print_tasks(&scheduler::tasks::<T>()?);
+            } else {
+                let _log_guard = logger::init(&LogConfig::from_config(&config)?)?;
+                runtime()?.block_on(scheduler::start::<T>(config, env))?;
+            }
+        }
+        Commands::Config { command } => match command {
+            ConfigCommands::Check {} => check_config::<T>(&config)?,
+            ConfigCommands::Print { json } => {
+                let value = config.redacted()?;
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&value).map_err(Error::wrap)?
+                    );
+                } else {
+                    print_config(&config, "", &value);
+                }
+            }
+            ConfigCommands::Get { key } => {
+                let value = config
+                    .get_optional::<serde_json::Value>(&key)?
+                    .ok_or_else(|| Error::Message(format!("`{key}` is not set")))?;
+                match redact(&key, value) {
+                    serde_json::Value::String(s) => println!("{s}"),
+                    value => println!(
+                        "{}",
+                        serde_json::to_string_pretty(&value).map_err(Error::wrap)?
+                    ),
+                }
+                if let Some(origin) = config.origin(&key) {
+                    eprintln!("# from {origin}");
+                }
+            }
+        },
+        Commands::Stop {} => daemon::stop(&DaemonConfig::from_config(&config)?)?,
+        Commands::Status {} => daemon::status(&DaemonConfig::from_config(&config)?)?,
+        Commands::Routes { json } => {
+            let (ctx, routes) = runtime()?.block_on(build_routes::<T>(config, env))?;
+            let middlewares = routes.middleware_names(&ctx)?;
+            let routes = routes.collect();
+
+            if json {
+                #[derive(Serialize)]
+                struct Output<'a> {
+                    middlewares: Vec<&'a str>,
+                    routes: Vec<RouteInfo>,
+                }
+
+                let output = Output {
+                    middlewares,
+                    routes: routes.iter().map(ListRoutes::describe).collect(),
+                };
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&output).map_err(Error::wrap)?
+                );
+            } else {
+                print_routes(&middlewares, &routes);
+            }
         }
     }
 
     Ok(())
 }
+
+fn runtime() -> Result<tokio::runtime::Runtime> {
+    Ok(tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?)
+}
+
+fn print_routes<T: AppTrait>(middlewares: &[&str], routes: &[ListRoutes<T>]) {
+    let rows = routes
+        .iter()
+        .map(|route| {
+            [
+                route.actions_label(),
+                route.uri.clone(),
+                route.layers.join(","),
+                route.handler_names.join(", "),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print_table(["METHOD", "PATH", "LAYERS", "HANDLER"], &rows);
+
+    if !middlewares.is_empty() {
+        println!("\nMiddlewares (inner to outer): {}", middlewares.join(", "));
+    }
+}
+
+/// Print the result of [`app::check_config`].
+fn check_config<T: AppTrait>(config: &Config) -> Result<()> {
+    let check = app::check_config::<T>(config);
+
+    for (key, error) in check.results() {
+        match error {
+            None => println!("ok     {key}"),
+            Some(error) => println!("error  {key}: {error}"),
+        }
+    }
+
+    match check.error_count() {
+        0 => Ok(()),
+        count => Err(Error::Message(format!("{count} config error(s)"))),
+    }
+}
+
+/// Print the values of `value` as `key = value  # layer` lines.
+fn print_config(config: &Config, key: &str, value: &serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (k, value) in map {
+                let path = if key.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{key}.{k}")
+                };
+                print_config(config, &path, value);
+            }
+        }
+        value => println!("{key} = {value}  # {}", config.origin(key).unwrap_or("?")),
+    }
+}
+
+fn print_tasks<T: AppTrait>(tasks: &Tasks<T>) {
+    let now = chrono::Local::now();
+    let rows = tasks
+        .iter()
+        .map(|task| {
+            [
+                task.name().to_string(),
+                task.schedule().to_string(),
+                if task.is_single_node() {
+                    "single node"
+                } else {
+                    "every node"
+                }
+                .to_string(),
+                format!("{:?}", task.jitter_max()),
+                task.schedule()
+                    .next_after(now)
+                    .map_or_else(|| "never".to_string(), |next| next.to_rfc3339()),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print_table(["TASK", "SCHEDULE", "MODE", "JITTER", "NEXT RUN"], &rows);
+}
+
+/// Print `rows` in columns aligned under `header`.
+fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let header = header.map(ToString::to_string);
+    let mut widths = header.clone().map(|h| h.len());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
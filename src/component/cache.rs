@@ -0,0 +1,356 @@
+//! 带类型的缓存，值以 JSON 保存在 Redis 或进程内存中
+
+use dashmap::DashMap;
+use redis::cluster_async::ClusterConnection;
+use redis::cluster_routing::{MultipleNodeRoutingInfo, RoutingInfo, SingleNodeRoutingInfo};
+use redis::{AsyncCommands, Value};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::component::redis::{AnyConnection, AnyRedisPool};
+use crate::component::{ComponentProvider, ComponentRegister};
+use crate::error::{Error, Result};
+
+/// Number of keys asked to `SCAN` per round trip by [`Cache::delete_prefix`]
+const SCAN_COUNT: usize = 100;
+
+/// The `[cache]` section
+#[derive(Serialize, Deserialize)]
+pub struct Config {
+    /// Where the values are stored
+    #[serde(default)]
+    pub backend: Backend,
+
+    /// Instance of the `AnyRedisPool` component used by the `redis` backend,
+    /// the default instance when not set
+    pub redis: Option<String>,
+
+    /// Prefix of the keys, defaults to the application name. Set it to an
+    /// empty string to disable the namespacing
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The `AnyRedisPool` component
+    #[default]
+    Redis,
+    /// The memory of the process, for tests
+    Memory,
+}
+
+/// A cache of serializable values, keys are prefixed with `<namespace>:`.
+///
+/// ```ignore
+/// let user: User = cache
+///     .get_or_insert_with("user:1", Some(Duration::from_secs(60)), || load_user(1))
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub struct Cache {
+    namespace: String,
+    store: Store,
+}
+
+#[derive(Clone)]
+enum Store {
+    Redis(Box<AnyRedisPool>),
+    /// Key to the JSON value and its expiry
+    Memory(Arc<DashMap<String, (String, Option<Instant>)>>),
+}
+
+impl Cache {
+    /// Create a cache backed by Redis.
+    #[must_use]
+    pub fn redis(pool: AnyRedisPool, namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            store: Store::Redis(Box::new(pool)),
+        }
+    }
+
+    /// Create a cache in the memory of the process.
+    #[must_use]
+    pub fn memory(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            store: Store::Memory(Arc::default()),
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        if self.namespace.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}:{key}", self.namespace)
+        }
+    }
+
+    /// Get the value of `key`.
+    ///
+    /// # Errors
+    /// Return an error when the store fails or the value could not be
+    /// deserialized into `T`.
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let key = self.key(key);
+
+        let value = match &self.store {
+            Store::Redis(pool) => {
+                let mut conn = pool.acquire().await?;
+                conn.get::<_, Option<String>>(&key).await?
+            }
+            Store::Memory(map) => {
+                let value = map.get(&key).and_then(|entry| {
+                    let (value, expires_at) = entry.value();
+                    match expires_at {
+                        Some(expires_at) if *expires_at <= Instant::now() => None,
+                        _ => Some(value.clone()),
+                    }
+                });
+                if value.is_none() {
+                    map.remove_if(&key, |_, (_, expires_at)| {
+                        expires_at.is_some_and(|at| at <= Instant::now())
+                    });
+                }
+                value
+            }
+        };
+
+        value
+            .map(|value| serde_json::from_str(&value).map_err(Error::wrap))
+            .transpose()
+    }
+
+    /// Set the value of `key`, expiring after `ttl` when given.
+    ///
+    /// # Errors
+    /// Return an error when the value could not be serialized or the store
+    /// fails.
+    pub async fn set<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        let key = self.key(key);
+        let value = serde_json::to_string(value).map_err(Error::wrap)?;
+
+        match &self.store {
+            Store::Redis(pool) => {
+                let mut conn = pool.acquire().await?;
+                match ttl {
+                    Some(ttl) => {
+                        let millis = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1);
+                        conn.pset_ex::<_, _, ()>(&key, value, millis).await?;
+                    }
+                    None => conn.set::<_, _, ()>(&key, value).await?,
+                }
+            }
+            Store::Memory(map) => {
+                map.insert(key, (value, ttl.map(|ttl| Instant::now() + ttl)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the value of `key`, or compute it with `f` and cache it when
+    /// missing. Concurrent callers may compute the value more than once.
+    ///
+    /// # Errors
+    /// Return an error when the store or `f` fails.
+    pub async fn get_or_insert_with<T, F, Fut>(
+        &self,
+        key: &str,
+        ttl: Option<Duration>,
+        f: F,
+    ) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if let Some(value) = self.get(key).await? {
+            return Ok(value);
+        }
+
+        let value = f().await?;
+        self.set(key, &value, ttl).await?;
+        Ok(value)
+    }
+
+    /// Delete `key`, return whether it existed.
+    ///
+    /// # Errors
+    /// Return an error when the store fails.
+    pub async fn delete(&self, key: &str) -> Result<bool> {
+        let key = self.key(key);
+
+        match &self.store {
+            Store::Redis(pool) => {
+                let mut conn = pool.acquire().await?;
+                Ok(conn.del::<_, u64>(&key).await? > 0)
+            }
+            Store::Memory(map) => Ok(map.remove(&key).is_some()),
+        }
+    }
+
+    /// Delete every key starting with `prefix`, return the number of deleted
+    /// keys. Every master of a Redis cluster is scanned.
+    ///
+    /// # Errors
+    /// Return an error when the store fails.
+    pub async fn delete_prefix(&self, prefix: &str) -> Result<u64> {
+        let prefix = self.key(prefix);
+
+        match &self.store {
+            Store::Redis(pool) => {
+                let pattern = format!("{}*", escape_pattern(&prefix));
+                let mut conn = pool.acquire().await?;
+
+                // a SCAN only walks the keys of the node it is sent to
+                let nodes = match &mut *conn {
+                    AnyConnection::Cluster(cluster) => cluster_masters(cluster)
+                        .await?
+                        .into_iter()
+                        .map(Some)
+                        .collect(),
+                    _ => vec![None],
+                };
+
+                let mut deleted = 0;
+                for node in nodes {
+                    let keys = scan(&mut conn, &pattern, node).await?;
+                    // the keys of a cluster node span several slots, delete
+                    // them one by one there to avoid CROSSSLOT errors
+                    let batch = if matches!(*conn, AnyConnection::Cluster(_)) {
+                        1
+                    } else {
+                        SCAN_COUNT
+                    };
+                    for keys in keys.chunks(batch) {
+                        deleted += conn.del::<_, u64>(keys).await?;
+                    }
+                }
+                Ok(deleted)
+            }
+            Store::Memory(map) => {
+                let mut deleted = 0;
+                map.retain(|key, _| {
+                    let matched = key.starts_with(&prefix);
+                    deleted += u64::from(matched);
+                    !matched
+                });
+                Ok(deleted)
+            }
+        }
+    }
+}
+
+/// Addresses of the masters of a cluster.
+async fn cluster_masters(cluster: &mut ClusterConnection) -> Result<Vec<(String, u16)>> {
+    // with no response policy the replies are keyed by the node addresses
+    let replies = cluster
+        .route_command(
+            &redis::cmd("PING"),
+            RoutingInfo::MultiNode((MultipleNodeRoutingInfo::AllMasters, None)),
+        )
+        .await?;
+    let replies: HashMap<String, Value> = redis::from_owned_redis_value(replies)?;
+
+    replies
+        .into_keys()
+        .map(|address| {
+            address
+                .rsplit_once(':')
+                .and_then(|(host, port)| Some((host.to_string(), port.parse().ok()?)))
+                .ok_or_else(|| Error::Message(format!("invalid redis node address `{address}`")))
+        })
+        .collect()
+}
+
+/// Collect the keys matching `pattern`, on the cluster master `node` when
+/// given.
+async fn scan(
+    conn: &mut AnyConnection,
+    pattern: &str,
+    node: Option<(String, u16)>,
+) -> Result<Vec<String>> {
+    let mut keys = vec![];
+    let mut cursor: u64 = 0;
+    loop {
+        let mut cmd = redis::cmd("SCAN");
+        cmd.arg(cursor)
+            .arg("MATCH")
+            .arg(pattern)
+            .arg("COUNT")
+            .arg(SCAN_COUNT);
+
+        let (next, batch): (u64, Vec<String>) = match (&mut *conn, &node) {
+            (AnyConnection::Cluster(cluster), Some((host, port))) => {
+                let route = RoutingInfo::SingleNode(SingleNodeRoutingInfo::ByAddress {
+                    host: host.clone(),
+                    port: *port,
+                });
+                redis::from_owned_redis_value(cluster.route_command(&cmd, route).await?)?
+            }
+            (conn, _) => cmd.query_async(conn).await?,
+        };
+        keys.extend(batch);
+
+        cursor = next;
+        if cursor == 0 {
+            return Ok(keys);
+        }
+    }
+}
+
+/// Escape the glob characters of a `SCAN MATCH` pattern.
+fn escape_pattern(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[async_trait::async_trait]
+impl ComponentProvider for Cache {
+    type Error = Error;
+
+    type Config = Config;
+
+    fn config_key() -> &'static str {
+        "cache"
+    }
+
+    async fn create(config: Self::Config, component_register: &ComponentRegister) -> Result<Self> {
+        let namespace = config
+            .namespace
+            .or_else(|| component_register.app_name().map(ToString::to_string))
+            .unwrap_or_default();
+
+        Ok(match config.backend {
+            Backend::Redis => {
+                let pool = match &config.redis {
+                    Some(name) => {
+                        component_register
+                            .component_named::<AnyRedisPool>(name)
+                            .await?
+                    }
+                    None => component_register.component::<AnyRedisPool>().await?,
+                };
+                Self::redis(pool, namespace)
+            }
+            Backend::Memory => Self::memory(namespace),
+        })
+    }
+}
@@ -1,8 +1,7 @@
+use crate::component::{ComponentProvider, HealthStatus};
+use sea_orm::{ConnectOptions, Database, DbConn};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
-use async_trait::async_trait;
-use crate::component::ComponentProvider;
-use sea_orm::{ConnectOptions, Database, DbConn};
 
 pub use sea_orm::DbConn as DB;
 
@@ -42,7 +41,7 @@ impl ComponentProvider for DbConn {
 
     async fn create(
         config: Self::Config,
-        _: &mut crate::component::ComponentRegister,
+        _: &crate::component::ComponentRegister,
     ) -> Result<Self, Self::Error> {
         let mut options = ConnectOptions::new(&config.uri);
 
@@ -68,4 +67,15 @@ impl ComponentProvider for DbConn {
 
         Database::connect(options).await
     }
+
+    async fn health(&self) -> HealthStatus {
+        self.ping().await.into()
+    }
+
+    async fn shutdown(&self) {
+        // the pool is shared by the clones, closing one closes them all
+        if let Err(err) = self.clone().close().await {
+            tracing::warn!(error = %err, "failed to close database connections");
+        }
+    }
 }
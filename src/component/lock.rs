@@ -0,0 +1,239 @@
+//! 基于 Redis 的分布式锁，`SET NX PX` 加锁并返回递增的 fencing token
+
+use redis::Script;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+use crate::component::redis::AnyRedisPool;
+use crate::error::{Error, Result};
+
+/// Wait between two attempts of [`DistributedLock::acquire`]
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Set the lock and increment the fencing counter in one step
+static ACQUIRE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2]) then
+            return redis.call('INCR', KEYS[2])
+        end
+        return 0
+        ",
+    )
+});
+
+/// Only touch the lock when it is still owned by the caller
+static EXTEND: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+        end
+        return 0
+        ",
+    )
+});
+
+static RELEASE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            return redis.call('DEL', KEYS[1])
+        end
+        return 0
+        ",
+    )
+});
+
+/// A lock shared by every process using the same Redis, e.g.
+///
+/// ```ignore
+/// let lock = DistributedLock::new(pool, "report:daily", Duration::from_secs(30));
+/// if let Some(guard) = lock.try_acquire().await? {
+///     write_report(guard.fencing_token()).await?;
+///     guard.release().await?;
+/// }
+/// ```
+///
+/// The lock expires after `ttl` unless its [`LockGuard`] is alive, the guard
+/// extends it in the background. Storage written under the lock should
+/// reject writes with a fencing token lower than the last one it saw, a
+/// paused holder may lose the lock without noticing.
+#[derive(Clone)]
+pub struct DistributedLock {
+    pool: AnyRedisPool,
+    key: String,
+    fencing_key: String,
+    ttl: Duration,
+}
+
+impl DistributedLock {
+    /// Create the lock `name`, the keys share a hash tag so they live in the
+    /// same slot of a cluster.
+    #[must_use]
+    pub fn new(pool: AnyRedisPool, name: &str, ttl: Duration) -> Self {
+        Self {
+            pool,
+            key: format!("lock:{{{name}}}"),
+            fencing_key: format!("lock:{{{name}}}:fencing"),
+            ttl,
+        }
+    }
+
+    /// Take the lock if it is free.
+    ///
+    /// # Errors
+    /// Return an error when Redis fails.
+    pub async fn try_acquire(&self) -> Result<Option<LockGuard>> {
+        let token = uuid::Uuid::new_v4().to_string();
+
+        let mut conn = self.pool.acquire().await?;
+        let fencing_token: u64 = ACQUIRE
+            .key(&self.key)
+            .key(&self.fencing_key)
+            .arg(&token)
+            .arg(ttl_millis(self.ttl))
+            .invoke_async(&mut *conn)
+            .await?;
+
+        if fencing_token == 0 {
+            return Ok(None);
+        }
+
+        let lost = Arc::new(AtomicBool::new(false));
+        let extender = tokio::spawn(extend(self.clone(), token.clone(), lost.clone()));
+
+        Ok(Some(LockGuard {
+            lock: self.clone(),
+            token,
+            fencing_token,
+            lost,
+            extender: Some(extender),
+        }))
+    }
+
+    /// Wait up to `timeout` for the lock.
+    ///
+    /// # Errors
+    /// Return an error when Redis fails or the lock is still held by another
+    /// owner after `timeout`.
+    pub async fn acquire(&self, timeout: Duration) -> Result<LockGuard> {
+        let started = Instant::now();
+        loop {
+            if let Some(guard) = self.try_acquire().await? {
+                return Ok(guard);
+            }
+            if started.elapsed() >= timeout {
+                return Err(Error::Message(format!(
+                    "lock `{}` not acquired within {timeout:?}",
+                    self.key
+                )));
+            }
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+    }
+}
+
+fn ttl_millis(ttl: Duration) -> u64 {
+    u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1)
+}
+
+/// Extend the lock every third of its ttl until the guard stops the task or
+/// the lock is lost.
+async fn extend(lock: DistributedLock, token: String, lost: Arc<AtomicBool>) {
+    let mut interval = tokio::time::interval((lock.ttl / 3).max(Duration::from_millis(1)));
+    // the first tick completes immediately
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let extended: Result<u64> = async {
+            let mut conn = lock.pool.acquire().await?;
+            Ok(EXTEND
+                .key(&lock.key)
+                .arg(&token)
+                .arg(ttl_millis(lock.ttl))
+                .invoke_async(&mut *conn)
+                .await?)
+        }
+        .await;
+
+        match extended {
+            Ok(0) => {
+                tracing::warn!(lock = lock.key, "lock lost before release");
+                lost.store(true, Ordering::SeqCst);
+                return;
+            }
+            Ok(_) => {}
+            // retried on the next tick, the lock survives until its ttl
+            Err(err) => tracing::warn!(lock = lock.key, error = %err, "failed to extend lock"),
+        }
+    }
+}
+
+/// Ownership of a [`DistributedLock`], released on [`LockGuard::release`] or
+/// in the background when dropped.
+pub struct LockGuard {
+    lock: DistributedLock,
+    token: String,
+    fencing_token: u64,
+    lost: Arc<AtomicBool>,
+    extender: Option<JoinHandle<()>>,
+}
+
+impl LockGuard {
+    /// Increases every time the lock is acquired
+    #[must_use]
+    pub fn fencing_token(&self) -> u64 {
+        self.fencing_token
+    }
+
+    /// Whether the lock expired or was taken over while the guard is alive.
+    #[must_use]
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::SeqCst)
+    }
+
+    /// Release the lock, return `false` when it was already lost.
+    ///
+    /// # Errors
+    /// Return an error when Redis fails, the lock then expires after its ttl.
+    pub async fn release(mut self) -> Result<bool> {
+        if let Some(extender) = self.extender.take() {
+            extender.abort();
+        }
+        release(&self.lock, &self.token).await
+    }
+}
+
+async fn release(lock: &DistributedLock, token: &str) -> Result<bool> {
+    let mut conn = lock.pool.acquire().await?;
+    let released: u64 = RELEASE
+        .key(&lock.key)
+        .arg(token)
+        .invoke_async(&mut *conn)
+        .await?;
+    Ok(released > 0)
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let Some(extender) = self.extender.take() else {
+            return;
+        };
+        extender.abort();
+
+        let lock = self.lock.clone();
+        let token = std::mem::take(&mut self.token);
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move {
+                if let Err(err) = release(&lock, &token).await {
+                    tracing::warn!(lock = lock.key, error = %err, "failed to release lock");
+                }
+            });
+        }
+    }
+}
@@ -1,66 +1,437 @@
-use std::any::{Any, TypeId};
-use config::Config;
+use crate::app::{AppContext, AppTrait};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
 use dashmap::DashMap;
 use serde::de::DeserializeOwned;
+use std::any::{type_name, Any, TypeId};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{watch, OnceCell};
 
+pub mod cache;
+#[cfg(feature = "with-db")]
+pub mod database;
+pub mod lock;
+pub mod rate_limit;
 pub mod redis;
 pub mod session;
-mod database;
 
+/// A created component with its type erased, used to drive the lifecycle
+/// hooks without knowing the concrete type.
+#[async_trait]
+pub(crate) trait AnyComponent: Send + Sync {
+    fn as_any(&self) -> &(dyn Any + Send + Sync);
+
+    /// Only called by the health route
+    #[cfg_attr(not(feature = "with-db"), allow(dead_code))]
+    async fn health(&self) -> HealthStatus;
+
+    async fn shutdown(&self);
+}
+
+#[async_trait]
+impl<T> AnyComponent for T
+where
+    T: ComponentProvider + 'static,
+{
+    fn as_any(&self) -> &(dyn Any + Send + Sync) {
+        self
+    }
+
+    async fn health(&self) -> HealthStatus {
+        ComponentProvider::health(self).await
+    }
+
+    async fn shutdown(&self) {
+        ComponentProvider::shutdown(self).await;
+    }
+}
+
+/// A component instance created by the register
+pub(crate) struct CreatedComponent {
+    /// Config key of the instance, e.g. `database` or `database.replica`
+    pub name: String,
+    pub component: Box<dyn AnyComponent>,
+}
+
+/// Identify a component instance, `name` is `None` for the default instance.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ComponentKey {
+    type_id: TypeId,
+    name: Option<String>,
+}
+
+/// Create components from the config and keep one instance of each type, or
+/// one instance of each type and name for named instances.
+///
+/// The default instance of a component is configured by the
+/// [`ComponentProvider::config_key`] section, e.g. `[database]`, a named
+/// instance by a sub-section of it, e.g. `[database.replica]`.
+///
+/// Components are resolved through `&self`, concurrent callers of
+/// [`ComponentRegister::component`] wait for a single creation. A component
+/// depending on itself through [`ComponentProvider::create`] fails with
+/// [`Error::ComponentCycle`] instead of recursing forever, also when the
+/// components of the cycle are created by different tasks at the same time.
+///
+/// [`ComponentRegister::shutdown`] shuts the components down in the reverse
+/// order of their creation, a component is shut down before the components
+/// it depends on.
 pub struct ComponentRegister {
+    inner: Arc<RegisterInner>,
+    /// Components being created by the current resolution chain, with their
+    /// label used in errors
+    resolving: Vec<(ComponentKey, String)>,
+}
+
+struct RegisterInner {
     config: Config,
-    created_components: DashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    /// [`AppTrait::app_name`] of the application owning the register
+    app_name: Option<&'static str>,
+    created_components: DashMap<ComponentKey, Arc<OnceCell<Arc<CreatedComponent>>>>,
+    /// Created components, in creation order
+    created_order: Mutex<Vec<ComponentKey>>,
+    /// The config reloaded at runtime, see [`ComponentRegister::subscribe_config`]
+    config_updates: watch::Sender<Arc<Config>>,
+    /// Components waiting for the creation of another one, to detect the
+    /// cycles spanning several tasks, which would wait for each other forever
+    waits: Mutex<Vec<Wait>>,
+}
+
+/// The creation of `from` waits for the component `to`, with their labels
+#[derive(Clone)]
+struct Wait {
+    from: (ComponentKey, String),
+    to: (ComponentKey, String),
+}
+
+/// Remove a [`Wait`] once the awaited component is resolved
+struct WaitGuard {
+    inner: Arc<RegisterInner>,
+    from: ComponentKey,
+    to: ComponentKey,
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        let mut waits = self
+            .inner
+            .waits
+            .lock()
+            .expect("component waits lock poisoned");
+        if let Some(index) = waits
+            .iter()
+            .position(|wait| wait.from.0 == self.from && wait.to.0 == self.to)
+        {
+            waits.swap_remove(index);
+        }
+    }
 }
 
 impl ComponentRegister {
     pub fn new(config: Config) -> Self {
+        Self::create(config, None)
+    }
+
+    /// Create the register of the application `app_name`, components may use
+    /// the name e.g. to namespace their keys.
+    pub fn with_app_name(config: Config, app_name: &'static str) -> Self {
+        Self::create(config, Some(app_name))
+    }
+
+    fn create(config: Config, app_name: Option<&'static str>) -> Self {
         Self {
-            config,
-            created_components: DashMap::new(),
+            inner: Arc::new(RegisterInner {
+                config_updates: watch::Sender::new(Arc::new(config.clone())),
+                config,
+                app_name,
+                created_components: DashMap::new(),
+                created_order: Mutex::default(),
+                waits: Mutex::default(),
+            }),
+            resolving: vec![],
         }
     }
 
-    pub async fn component<T>(&mut self) -> crate::error::Result<T>
+    /// Name of the application owning the register.
+    pub fn app_name(&self) -> Option<&'static str> {
+        self.inner.app_name
+    }
+
+    /// Receive the config each time it is reloaded, starting with the current
+    /// one, when `[hot_reload]` is enabled in a long-running command. Components are created from the
+    /// boot config, a component applies the changes it supports itself, e.g.
+    ///
+    /// ```ignore
+    /// let mut configs = register.subscribe_config();
+    /// tokio::spawn(async move {
+    ///     while configs.changed().await.is_ok() {
+    ///         let config = configs.borrow_and_update().clone();
+    ///         apply_limits(&config);
+    ///     }
+    /// });
+    /// ```
+    pub fn subscribe_config(&self) -> watch::Receiver<Arc<Config>> {
+        self.inner.config_updates.subscribe()
+    }
+
+    /// Publish a reloaded config to the subscribers.
+    pub(crate) fn publish_config(&self, config: Config) {
+        self.inner.config_updates.send_replace(Arc::new(config));
+    }
+
+    /// Get the default instance of the component, creating it from its config
+    /// section on first use.
+    ///
+    /// # Errors
+    /// Return an error when the config is missing and has required options,
+    /// is invalid, the creation fails, or the component depends on itself.
+    pub async fn component<T>(&self) -> Result<T>
     where
         T: ComponentProvider + 'static,
     {
-        if let Some(component) = self.get::<T>().await {
-            return Ok(component);
+        self.resolve::<T>(None).await
+    }
+
+    /// Get the instance `name` of the component, creating it from the
+    /// `[<config_key>.<name>]` section on first use, e.g.
+    /// `component_named::<DbConn>("replica")` reads `[database.replica]`.
+    ///
+    /// # Errors
+    /// Same as [`ComponentRegister::component`].
+    pub async fn component_named<T>(&self, name: &str) -> Result<T>
+    where
+        T: ComponentProvider + 'static,
+    {
+        self.resolve::<T>(Some(name)).await
+    }
+
+    async fn resolve<T>(&self, name: Option<&str>) -> Result<T>
+    where
+        T: ComponentProvider + 'static,
+    {
+        let key = Self::key::<T>(name);
+        let label = name.map_or_else(
+            || type_name::<T>().to_string(),
+            |name| format!("{}({name})", type_name::<T>()),
+        );
+
+        if self.resolving.iter().any(|(k, _)| *k == key) {
+            let cycle = self
+                .resolving
+                .iter()
+                .skip_while(|(k, _)| *k != key)
+                .map(|(_, label)| label.clone())
+                .chain([label])
+                .collect();
+            return Err(Error::ComponentCycle(cycle));
         }
 
-        let component = T::create(self.config.get::<T::Config>(T::config_key())?, self)
-            .await
-            .map_err(|err| err.into())?;
+        // another task creating the component may be waiting for one being
+        // created by this chain
+        let _wait = match self.resolving.last() {
+            Some(parent) => Some(self.wait_for(parent.clone(), (key.clone(), label.clone()))?),
+            None => None,
+        };
+
+        // do not hold the map guard across the creation, it may resolve
+        // other components
+        let cell = self
+            .inner
+            .created_components
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        let created = cell
+            .get_or_try_init(|| async {
+                let config_key = name.map_or_else(
+                    || T::config_key().to_string(),
+                    |name| format!("{}.{name}", T::config_key()),
+                );
+                let config = match self.inner.config.get_optional::<T::Config>(&config_key)? {
+                    Some(config) => config,
+                    // the default instance needs no section when every
+                    // option has a default, e.g. `[worker]`
+                    None if name.is_none() => serde_json::from_value(serde_json::json!({}))
+                        .or_else(|_| self.inner.config.get::<T::Config>(&config_key))?,
+                    None => self.inner.config.get::<T::Config>(&config_key)?,
+                };
+
+                let mut resolving = self.resolving.clone();
+                resolving.push((key.clone(), label.clone()));
+                let register = Self {
+                    inner: self.inner.clone(),
+                    resolving,
+                };
+
+                tracing::debug!(component = label, "creating component");
+                let component = T::create(config, &register).await.map_err(Into::into)?;
+
+                // dependencies finish their creation first and come earlier
+                self.inner
+                    .created_order
+                    .lock()
+                    .expect("component order lock poisoned")
+                    .push(key.clone());
+
+                Ok::<_, Error>(Arc::new(CreatedComponent {
+                    name: config_key,
+                    component: Box::new(component),
+                }))
+            })
+            .await?;
+
+        Ok(Self::downcast::<T>(created))
+    }
 
-        self.created_components
-            .insert(TypeId::of::<T>(), Box::new(component.clone()));
+    /// Record that the creation of `from` waits for `to`, unless `to` already
+    /// waits for `from` through the other creations in progress.
+    fn wait_for(
+        &self,
+        from: (ComponentKey, String),
+        to: (ComponentKey, String),
+    ) -> Result<WaitGuard> {
+        let mut waits = self
+            .inner
+            .waits
+            .lock()
+            .expect("component waits lock poisoned");
 
-        Ok(component)
+        // depth first search of a path from `to` back to `from`
+        let mut stack = vec![vec![to.clone()]];
+        let mut visited = vec![];
+        while let Some(path) = stack.pop() {
+            let (last, _) = path.last().expect("paths are not empty");
+            if *last == from.0 {
+                let cycle = [from.1]
+                    .into_iter()
+                    .chain(path.into_iter().map(|(_, label)| label))
+                    .collect();
+                return Err(Error::ComponentCycle(cycle));
+            }
+            if visited.contains(last) {
+                continue;
+            }
+            visited.push(last.clone());
+
+            for wait in waits.iter().filter(|wait| wait.from.0 == *last) {
+                let mut next = path.clone();
+                next.push(wait.to.clone());
+                stack.push(next);
+            }
+        }
+
+        let guard = WaitGuard {
+            inner: self.inner.clone(),
+            from: from.0.clone(),
+            to: to.0.clone(),
+        };
+        waits.push(Wait { from, to });
+        Ok(guard)
     }
 
+    /// Get the default instance of the component if it was already created.
     pub async fn get<T>(&self) -> Option<T>
     where
         T: ComponentProvider + 'static,
     {
-        if self.created_components.contains_key(&TypeId::of::<T>()) {
-            let created_component = self
-                .created_components
-                .get(&TypeId::of::<T>())
-                .expect("Component not found")
-                .value()
-                .downcast_ref::<T>()
-                .unwrap()
-                .clone();
-            return Some(created_component);
+        self.get_created::<T>(None)
+    }
+
+    /// Get the instance `name` of the component if it was already created.
+    pub async fn get_named<T>(&self, name: &str) -> Option<T>
+    where
+        T: ComponentProvider + 'static,
+    {
+        self.get_created::<T>(Some(name))
+    }
+
+    pub(crate) fn get_created<T>(&self, name: Option<&str>) -> Option<T>
+    where
+        T: ComponentProvider + 'static,
+    {
+        let cell = self
+            .inner
+            .created_components
+            .get(&Self::key::<T>(name))?
+            .clone();
+
+        cell.get().map(|created| Self::downcast::<T>(created))
+    }
+
+    /// The created components, in creation order.
+    pub(crate) fn created(&self) -> Vec<Arc<CreatedComponent>> {
+        let order = self
+            .inner
+            .created_order
+            .lock()
+            .expect("component order lock poisoned")
+            .clone();
+
+        order
+            .iter()
+            .filter_map(|key| {
+                let cell = self.inner.created_components.get(key)?.clone();
+                cell.get().cloned()
+            })
+            .collect()
+    }
+
+    /// Shut the created components down in the reverse order of their
+    /// creation and remove them from the register.
+    ///
+    /// Called once the server stopped accepting requests. Clones of a
+    /// component held elsewhere stay usable but should not be used anymore.
+    pub async fn shutdown(&self) {
+        let mut components = self.created();
+        components.reverse();
+
+        {
+            let mut order = self
+                .inner
+                .created_order
+                .lock()
+                .expect("component order lock poisoned");
+            for key in order.drain(..) {
+                self.inner.created_components.remove(&key);
+            }
         }
 
-        None
+        for created in components {
+            let started = Instant::now();
+            created.component.shutdown().await;
+            tracing::debug!(
+                component = created.name,
+                elapsed_ms = started.elapsed().as_millis(),
+                "component shut down"
+            );
+        }
+    }
+
+    fn key<T: 'static>(name: Option<&str>) -> ComponentKey {
+        ComponentKey {
+            type_id: TypeId::of::<T>(),
+            name: name.map(ToString::to_string),
+        }
+    }
+
+    fn downcast<T: 'static + Clone>(created: &CreatedComponent) -> T {
+        created
+            .component
+            .as_any()
+            .downcast_ref::<T>()
+            .expect("component stored under the TypeId of another type")
+            .clone()
     }
 }
 
 #[async_trait::async_trait]
 pub trait ComponentProvider: Sized + Send + Sync + Clone {
-    type Error: Into<crate::error::Error>;
+    type Error: Into<Error>;
 
     /// 配置的类型
     type Config: DeserializeOwned;
@@ -70,6 +441,221 @@ pub trait ComponentProvider: Sized + Send + Sync + Clone {
 
     async fn create(
         config: Self::Config,
-        component_register: &mut ComponentRegister,
-    ) -> Result<Self, Self::Error>;
+        component_register: &ComponentRegister,
+    ) -> std::result::Result<Self, Self::Error>;
+
+    /// Check whether the component is usable, reported by the health route.
+    async fn health(&self) -> HealthStatus {
+        HealthStatus::Unknown
+    }
+
+    /// Release the resources of the component, e.g. close the connections of
+    /// a pool. Called by [`ComponentRegister::shutdown`].
+    async fn shutdown(&self) {}
+}
+
+/// Health of a component, see [`ComponentProvider::health`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthStatus {
+    Up,
+    Down(String),
+    /// The component does not implement a health check
+    Unknown,
+}
+
+impl HealthStatus {
+    #[must_use]
+    pub fn is_down(&self) -> bool {
+        matches!(self, Self::Down(_))
+    }
+}
+
+impl<E: std::fmt::Display> From<std::result::Result<(), E>> for HealthStatus {
+    fn from(result: std::result::Result<(), E>) -> Self {
+        match result {
+            Ok(()) => Self::Up,
+            Err(err) => Self::Down(err.to_string()),
+        }
+    }
+}
+
+/// Extract a component of the application, e.g.
+/// `Component(db): Component<DbConn>`. Respond with `500 Internal Server
+/// Error` when the component could not be created.
+#[derive(Debug, Clone)]
+pub struct Component<C>(pub C);
+
+impl<C> Deref for Component<C> {
+    type Target = C;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<T, C> FromRequestParts<AppContext<T>> for Component<C>
+where
+    T: AppTrait,
+    C: ComponentProvider + 'static,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        _parts: &mut Parts,
+        state: &AppContext<T>,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        state.component::<C>().await.map(Component).map_err(|err| {
+            tracing::error!(error = %err, "failed to extract component");
+            Error::InternalServerError
+        })
+    }
+}
+
+/// Name of a component instance, used by [`NamedComponent`] e.g.
+///
+/// ```ignore
+/// struct Replica;
+///
+/// impl InstanceName for Replica {
+///     const NAME: &'static str = "replica";
+/// }
+/// ```
+pub trait InstanceName: Send + Sync + 'static {
+    const NAME: &'static str;
+}
+
+/// Extract a named instance of a component, e.g.
+/// `NamedComponent(db, _): NamedComponent<DbConn, Replica>`, see
+/// [`ComponentRegister::component_named`]. Respond with `500 Internal Server
+/// Error` when the component could not be created.
+#[derive(Debug, Clone)]
+pub struct NamedComponent<C, N>(pub C, pub PhantomData<N>);
+
+impl<C, N> Deref for NamedComponent<C, N> {
+    type Target = C;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<T, C, N> FromRequestParts<AppContext<T>> for NamedComponent<C, N>
+where
+    T: AppTrait,
+    C: ComponentProvider + 'static,
+    N: InstanceName,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        _parts: &mut Parts,
+        state: &AppContext<T>,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        state
+            .component_named::<C>(N::NAME)
+            .await
+            .map(|component| NamedComponent(component, PhantomData))
+            .map_err(|err| {
+                tracing::error!(error = %err, instance = N::NAME, "failed to extract component");
+                Error::InternalServerError
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::LazyLock;
+    use std::time::Duration;
+    use tokio::sync::Barrier;
+
+    /// Both creations are in progress before either resolves the other
+    static BARRIER: LazyLock<Barrier> = LazyLock::new(|| Barrier::new(2));
+    static A_WAITED: AtomicBool = AtomicBool::new(false);
+    static B_WAITED: AtomicBool = AtomicBool::new(false);
+
+    #[derive(Clone)]
+    struct A;
+
+    #[derive(Clone)]
+    struct B;
+
+    #[async_trait]
+    impl ComponentProvider for A {
+        type Error = Error;
+        type Config = HashMap<String, i64>;
+
+        fn config_key() -> &'static str {
+            "a"
+        }
+
+        async fn create(_: Self::Config, register: &ComponentRegister) -> Result<Self> {
+            if !A_WAITED.swap(true, Ordering::SeqCst) {
+                BARRIER.wait().await;
+            }
+            register.component::<B>().await?;
+            Ok(Self)
+        }
+    }
+
+    #[async_trait]
+    impl ComponentProvider for B {
+        type Error = Error;
+        type Config = HashMap<String, i64>;
+
+        fn config_key() -> &'static str {
+            "b"
+        }
+
+        async fn create(_: Self::Config, register: &ComponentRegister) -> Result<Self> {
+            if !B_WAITED.swap(true, Ordering::SeqCst) {
+                BARRIER.wait().await;
+            }
+            register.component::<A>().await?;
+            Ok(Self)
+        }
+    }
+
+    #[tokio::test]
+    async fn cycle_across_tasks_is_an_error() {
+        let register = ComponentRegister::new(Config::from_toml("a.x = 1\nb.x = 1"));
+
+        let resolved = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(register.component::<A>(), register.component::<B>())
+        })
+        .await
+        .expect("the creations wait for each other");
+
+        assert!(matches!(resolved.0, Err(Error::ComponentCycle(_))));
+        assert!(matches!(resolved.1, Err(Error::ComponentCycle(_))));
+    }
+
+    #[derive(Clone)]
+    struct C;
+
+    #[async_trait]
+    impl ComponentProvider for C {
+        type Error = Error;
+        type Config = HashMap<String, i64>;
+
+        fn config_key() -> &'static str {
+            "c"
+        }
+
+        async fn create(_: Self::Config, _: &ComponentRegister) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    #[tokio::test]
+    async fn default_config_without_section() {
+        let register = ComponentRegister::new(Config::from_toml(""));
+
+        assert!(register.component::<C>().await.is_ok());
+        assert!(register.component_named::<C>("x").await.is_err());
+    }
 }
@@ -0,0 +1,99 @@
+//! 基于 Redis 有序集合的滑动窗口限流
+
+use redis::Script;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::component::redis::AnyRedisPool;
+use crate::error::Result;
+
+/// Drop the hits out of the window, then record the hit when under the limit.
+/// Return `{allowed, remaining, retry_after_ms}`
+static HIT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        local now = tonumber(ARGV[1])
+        local window = tonumber(ARGV[2])
+        local limit = tonumber(ARGV[3])
+
+        redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+        local count = redis.call('ZCARD', KEYS[1])
+        if count < limit then
+            redis.call('ZADD', KEYS[1], now, ARGV[4])
+            redis.call('PEXPIRE', KEYS[1], window)
+            return {1, limit - count - 1, 0}
+        end
+
+        -- empty with a zero limit, every hit is rejected
+        local oldest = redis.call('ZRANGE', KEYS[1], 0, 0, 'WITHSCORES')
+        if oldest[2] == nil then
+            return {0, 0, window}
+        end
+        return {0, 0, tonumber(oldest[2]) + window - now}
+        ",
+    )
+});
+
+/// Outcome of [`RateLimiter::hit`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub allowed: bool,
+    /// Hits left in the current window
+    pub remaining: u64,
+    /// When the next hit is allowed, zero when allowed
+    pub retry_after: Duration,
+}
+
+/// Allow at most `limit` hits per key in any `window`, e.g. 100 requests per
+/// minute and client IP.
+///
+/// The limit is fixed at creation, it does not follow a reloaded config.
+#[derive(Clone)]
+pub struct RateLimiter {
+    pool: AnyRedisPool,
+    name: String,
+    limit: u64,
+    window: Duration,
+}
+
+impl RateLimiter {
+    /// Create the limiter `name`, used to prefix its keys. A zero `limit`
+    /// rejects every hit.
+    #[must_use]
+    pub fn new(pool: AnyRedisPool, name: &str, limit: u64, window: Duration) -> Self {
+        Self {
+            pool,
+            name: name.to_string(),
+            limit,
+            window,
+        }
+    }
+
+    /// Record a hit of `key` if allowed.
+    ///
+    /// # Errors
+    /// Return an error when Redis fails.
+    pub async fn hit(&self, key: &str) -> Result<RateLimit> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let window = self.window.as_millis().max(1);
+
+        let mut conn = self.pool.acquire().await?;
+        let (allowed, remaining, retry_after): (u64, u64, u64) = HIT
+            .key(format!("rate_limit:{}:{key}", self.name))
+            .arg(now.to_string())
+            .arg(window.to_string())
+            .arg(self.limit)
+            .arg(uuid::Uuid::new_v4().to_string())
+            .invoke_async(&mut *conn)
+            .await?;
+
+        Ok(RateLimit {
+            allowed: allowed == 1,
+            remaining,
+            retry_after: Duration::from_millis(retry_after),
+        })
+    }
+}
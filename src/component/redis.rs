@@ -1,14 +1,28 @@
 use futures::future::FutureExt;
 use redis::aio::ConnectionLike;
-use redis::{Cmd, Pipeline, RedisFuture, Value};
+use redis::sentinel::{SentinelClient, SentinelNodeConnectionInfo, SentinelServerType};
+use redis::{
+    AsyncConnectionConfig, ClientTlsConfig, Cmd, ConnectionAddr, ErrorKind, IntoConnectionInfo,
+    Pipeline, RedisConnectionInfo, RedisFuture, TlsCertificates, TlsMode, Value,
+};
 use redis_pool::factory::ConnectionFactory;
 use redis_pool::RedisPool;
 use serde::{Deserialize, Serialize};
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
 
-use crate::component::ComponentProvider;
+use crate::component::{ComponentProvider, HealthStatus};
 pub use redis::{AsyncCommands, RedisError, RedisResult};
 
-pub type AnyRedisPool = RedisPool<AnyClient, AnyConnection>;
+pub type AnyRedisPool = RedisPool<PoolFactory, AnyConnection>;
+
+/// Longest wait for the idle connections to be closed on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
@@ -20,6 +34,28 @@ pub struct Config {
 
     /// 连接限制
     pub connection_limit: Option<usize>,
+
+    /// Username, overrides the one of the URLs
+    pub username: Option<String>,
+
+    /// Password, overrides the one of the URLs
+    pub password: Option<String>,
+
+    /// Database index, overrides the one of the URLs. Must be `0` for a
+    /// cluster
+    pub db: Option<i64>,
+
+    /// Connect over TLS, also enabled by `rediss://` URLs
+    pub tls: Option<Tls>,
+
+    /// Timeout of establishing a connection, in milliseconds
+    pub connect_timeout: Option<u64>,
+
+    /// Timeout of waiting for a response, in milliseconds
+    pub response_timeout: Option<u64>,
+
+    /// Retry failed connection attempts with an exponential backoff
+    pub retry: Option<Retry>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -31,38 +67,342 @@ pub enum Connection {
     /// Redis 集群
     #[serde(rename = "cluster")]
     Cluster { urls: Vec<String> },
+    /// Redis Sentinel，连接到 `master_name` 当前的主节点
+    ///
+    /// `username`, `password` and `db` of [`Config`] apply to the master,
+    /// `tls` to the master and the sentinels. Custom TLS certificates are
+    /// not supported
+    #[serde(rename = "sentinel")]
+    Sentinel {
+        /// Name of the master monitored by the sentinels
+        master_name: String,
+        /// URLs of the sentinels, e.g. `redis://127.0.0.1:26379`
+        urls: Vec<String>,
+        /// Username of the sentinels, overrides the one of the URLs
+        sentinel_username: Option<String>,
+        /// Password of the sentinels, overrides the one of the URLs
+        sentinel_password: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Tls {
+    /// PEM file of the CA certificate, the system roots are used when not set
+    pub ca_cert: Option<PathBuf>,
+
+    /// PEM file of the client certificate, for mutual TLS
+    pub client_cert: Option<PathBuf>,
+
+    /// PEM file of the client key, for mutual TLS
+    pub client_key: Option<PathBuf>,
+
+    /// Skip the verification of the hostname
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+impl Tls {
+    /// Switch `addr` to TLS, keeping the parameters of a `rediss://` URL.
+    fn addr(&self, addr: ConnectionAddr) -> RedisResult<ConnectionAddr> {
+        match addr {
+            ConnectionAddr::Tcp(host, port) => Ok(ConnectionAddr::TcpTls {
+                host,
+                port,
+                insecure: self.insecure,
+                tls_params: None,
+            }),
+            ConnectionAddr::TcpTls {
+                host,
+                port,
+                tls_params,
+                ..
+            } => Ok(ConnectionAddr::TcpTls {
+                host,
+                port,
+                insecure: self.insecure,
+                tls_params,
+            }),
+            ConnectionAddr::Unix(_) => Err(RedisError::from((
+                ErrorKind::InvalidClientConfig,
+                "TLS is not supported over a unix socket",
+            ))),
+        }
+    }
+
+    fn certificates(&self) -> RedisResult<Option<TlsCertificates>> {
+        let client_tls = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => Some(ClientTlsConfig {
+                client_cert: fs::read(cert)?,
+                client_key: fs::read(key)?,
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(RedisError::from((
+                    ErrorKind::InvalidClientConfig,
+                    "`tls.client_cert` and `tls.client_key` must be set together",
+                )))
+            }
+        };
+        let root_cert = self.ca_cert.as_ref().map(fs::read).transpose()?;
+
+        if client_tls.is_none() && root_cert.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(TlsCertificates {
+            client_tls,
+            root_cert,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Retry {
+    /// Number of retries after the first failed attempt
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+
+    /// Wait before the first retry in milliseconds, doubled on each retry
+    #[serde(default = "default_min_wait")]
+    pub min_wait: u64,
+
+    /// Maximum wait between two retries in milliseconds
+    #[serde(default = "default_max_wait")]
+    pub max_wait: u64,
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn default_min_wait() -> u64 {
+    100
+}
+
+fn default_max_wait() -> u64 {
+    5000
+}
+
+/// Options of the connections created by [`AnyClient::get_connection`], the
+/// cluster client holds its own
+#[derive(Clone, Default)]
+pub struct ConnectOptions {
+    connect_timeout: Option<Duration>,
+    response_timeout: Option<Duration>,
+    retry: Option<Retry>,
+}
+
+impl ConnectOptions {
+    fn async_config(&self) -> AsyncConnectionConfig {
+        let mut config = AsyncConnectionConfig::new();
+        if let Some(timeout) = self.connect_timeout {
+            config = config.set_connection_timeout(timeout);
+        }
+        if let Some(timeout) = self.response_timeout {
+            config = config.set_response_timeout(timeout);
+        }
+        config
+    }
+
+    /// Call `connect` until it succeeds, an error which is not about the
+    /// network occurs, or the retries are exhausted.
+    async fn retry<T, F, Fut>(&self, mut connect: F) -> RedisResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = RedisResult<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let err = match connect().await {
+                Ok(conn) => return Ok(conn),
+                Err(err) => err,
+            };
+
+            let retryable = err.is_io_error() || err.is_timeout() || err.is_connection_refusal();
+            match &self.retry {
+                Some(retry) if retryable && attempt < retry.retries => {
+                    let wait = retry
+                        .min_wait
+                        .saturating_mul(2u64.saturating_pow(attempt))
+                        .min(retry.max_wait);
+                    tracing::warn!(
+                        attempt,
+                        wait_ms = wait,
+                        error = %err,
+                        "redis connection failed, retrying"
+                    );
+                    tokio::time::sleep(Duration::from_millis(wait)).await;
+                    attempt += 1;
+                }
+                _ => return Err(err),
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
 pub enum AnyClient {
-    Single(redis::Client),
+    Single(redis::Client, ConnectOptions),
     Cluster(redis::cluster::ClusterClient),
+    /// Asks the sentinels for the current master on every new connection
+    Sentinel(Arc<Mutex<SentinelClient>>, ConnectOptions),
 }
 
 impl AnyClient {
     pub fn new(config: &Config) -> RedisResult<Self> {
+        let options = ConnectOptions {
+            connect_timeout: config.connect_timeout.map(Duration::from_millis),
+            response_timeout: config.response_timeout.map(Duration::from_millis),
+            retry: config.retry.clone(),
+        };
+
         match &config.connection {
             Connection::Single { url } => {
-                let client = redis::Client::open(url.as_str())?;
-                Ok(AnyClient::Single(client))
+                let mut info = url.as_str().into_connection_info()?;
+                Self::apply_auth(config, &mut info.redis);
+
+                let certificates = match &config.tls {
+                    Some(tls) => {
+                        info.addr = tls.addr(info.addr)?;
+                        tls.certificates()?
+                    }
+                    None => None,
+                };
+
+                let client = match certificates {
+                    Some(certificates) => redis::Client::build_with_tls(info, certificates)?,
+                    None => redis::Client::open(info)?,
+                };
+                Ok(AnyClient::Single(client, options))
             }
             Connection::Cluster { urls } => {
-                let client = redis::cluster::ClusterClient::new(urls.clone())?;
-                Ok(AnyClient::Cluster(client))
+                if config.db.is_some_and(|db| db != 0) {
+                    return Err(RedisError::from((
+                        ErrorKind::InvalidClientConfig,
+                        "a redis cluster only has the database 0",
+                    )));
+                }
+
+                let mut builder = redis::cluster::ClusterClient::builder(urls.clone());
+                if let Some(username) = &config.username {
+                    builder = builder.username(username.clone());
+                }
+                if let Some(password) = &config.password {
+                    builder = builder.password(password.clone());
+                }
+                if let Some(tls) = &config.tls {
+                    builder = builder.tls(if tls.insecure {
+                        TlsMode::Insecure
+                    } else {
+                        TlsMode::Secure
+                    });
+                    if let Some(certificates) = tls.certificates()? {
+                        builder = builder.certs(certificates);
+                    }
+                }
+                if let Some(timeout) = options.connect_timeout {
+                    builder = builder.connection_timeout(timeout);
+                }
+                if let Some(timeout) = options.response_timeout {
+                    builder = builder.response_timeout(timeout);
+                }
+                if let Some(retry) = &options.retry {
+                    builder = builder
+                        .retries(retry.retries)
+                        .min_retry_wait(retry.min_wait)
+                        .max_retry_wait(retry.max_wait);
+                }
+                Ok(AnyClient::Cluster(builder.build()?))
+            }
+            Connection::Sentinel {
+                master_name,
+                urls,
+                sentinel_username,
+                sentinel_password,
+            } => {
+                if config.tls.as_ref().is_some_and(|tls| {
+                    tls.ca_cert.is_some() || tls.client_cert.is_some() || tls.client_key.is_some()
+                }) {
+                    return Err(RedisError::from((
+                        ErrorKind::InvalidClientConfig,
+                        "custom TLS certificates are not supported with sentinel",
+                    )));
+                }
+
+                let sentinels = urls
+                    .iter()
+                    .map(|url| {
+                        let mut info = url.as_str().into_connection_info()?;
+                        if let Some(username) = sentinel_username {
+                            info.redis.username = Some(username.clone());
+                        }
+                        if let Some(password) = sentinel_password {
+                            info.redis.password = Some(password.clone());
+                        }
+                        if let Some(tls) = &config.tls {
+                            info.addr = tls.addr(info.addr)?;
+                        }
+                        Ok(info)
+                    })
+                    .collect::<RedisResult<Vec<_>>>()?;
+
+                let mut redis_info = RedisConnectionInfo::default();
+                Self::apply_auth(config, &mut redis_info);
+
+                let node = SentinelNodeConnectionInfo {
+                    tls_mode: config.tls.as_ref().map(|tls| {
+                        if tls.insecure {
+                            TlsMode::Insecure
+                        } else {
+                            TlsMode::Secure
+                        }
+                    }),
+                    redis_connection_info: Some(redis_info),
+                };
+                let client = SentinelClient::build(
+                    sentinels,
+                    master_name.clone(),
+                    Some(node),
+                    SentinelServerType::Master,
+                )?;
+                Ok(AnyClient::Sentinel(Arc::new(Mutex::new(client)), options))
             }
         }
     }
 
+    fn apply_auth(config: &Config, info: &mut RedisConnectionInfo) {
+        if let Some(username) = &config.username {
+            info.username = Some(username.clone());
+        }
+        if let Some(password) = &config.password {
+            info.password = Some(password.clone());
+        }
+        if let Some(db) = config.db {
+            info.db = db;
+        }
+    }
+
     pub async fn get_connection(&self) -> RedisResult<AnyConnection> {
         match self {
-            AnyClient::Single(client) => {
-                let conn = client.get_multiplexed_tokio_connection().await?;
+            AnyClient::Single(client, options) => {
+                let config = options.async_config();
+                let conn = options
+                    .retry(|| client.get_multiplexed_async_connection_with_config(&config))
+                    .await?;
                 Ok(AnyConnection::Single(conn))
             }
             AnyClient::Cluster(client) => {
                 let conn = client.get_async_connection().await?;
                 Ok(AnyConnection::Cluster(conn))
             }
+            AnyClient::Sentinel(client, options) => {
+                let conn = SentinelConnection::connect(client, options).await?;
+                Ok(AnyConnection::Sentinel(SentinelConnection {
+                    client: client.clone(),
+                    options: options.clone(),
+                    conn,
+                    stale: false,
+                }))
+            }
         }
     }
 }
@@ -70,6 +410,57 @@ impl AnyClient {
 pub enum AnyConnection {
     Single(redis::aio::MultiplexedConnection),
     Cluster(redis::cluster_async::ClusterConnection),
+    Sentinel(SentinelConnection),
+}
+
+/// A connection to the master of a Sentinel, reconnected to the master
+/// currently known by the sentinels after a failover.
+///
+/// The command failing because of the failover still returns its error, the
+/// next command runs on the new master.
+pub struct SentinelConnection {
+    client: Arc<Mutex<SentinelClient>>,
+    options: ConnectOptions,
+    conn: redis::aio::MultiplexedConnection,
+    /// The last command failed in a way suggesting a failover
+    stale: bool,
+}
+
+impl SentinelConnection {
+    async fn connect(
+        client: &Mutex<SentinelClient>,
+        options: &ConnectOptions,
+    ) -> RedisResult<redis::aio::MultiplexedConnection> {
+        let config = options.async_config();
+        options
+            .retry(|| async {
+                client
+                    .lock()
+                    .await
+                    .get_async_connection_with_config(&config)
+                    .await
+            })
+            .await
+    }
+
+    async fn rediscover(&mut self) -> RedisResult<()> {
+        if self.stale {
+            tracing::info!("redis master may have changed, asking the sentinels");
+            self.conn = Self::connect(&self.client, &self.options).await?;
+            self.stale = false;
+        }
+        Ok(())
+    }
+
+    fn check<T>(&mut self, result: RedisResult<T>) -> RedisResult<T> {
+        if let Err(err) = &result {
+            // the old master is demoted to a replica or is gone
+            self.stale = err.kind() == ErrorKind::ReadOnly
+                || err.is_io_error()
+                || err.is_connection_dropped();
+        }
+        result
+    }
 }
 
 impl ConnectionLike for AnyConnection {
@@ -78,6 +469,11 @@ impl ConnectionLike for AnyConnection {
             match self {
                 AnyConnection::Single(conn) => conn.req_packed_command(cmd).await,
                 AnyConnection::Cluster(conn) => conn.req_packed_command(cmd).await,
+                AnyConnection::Sentinel(conn) => {
+                    conn.rediscover().await?;
+                    let result = conn.conn.req_packed_command(cmd).await;
+                    conn.check(result)
+                }
             }
         })
         .boxed()
@@ -93,6 +489,11 @@ impl ConnectionLike for AnyConnection {
             match self {
                 AnyConnection::Single(conn) => conn.req_packed_commands(cmd, offset, count).await,
                 AnyConnection::Cluster(conn) => conn.req_packed_commands(cmd, offset, count).await,
+                AnyConnection::Sentinel(conn) => {
+                    conn.rediscover().await?;
+                    let result = conn.conn.req_packed_commands(cmd, offset, count).await;
+                    conn.check(result)
+                }
             }
         })
         .boxed()
@@ -102,6 +503,7 @@ impl ConnectionLike for AnyConnection {
         match self {
             AnyConnection::Single(conn) => conn.get_db(),
             AnyConnection::Cluster(conn) => conn.get_db(),
+            AnyConnection::Sentinel(conn) => conn.conn.get_db(),
         }
     }
 }
@@ -113,10 +515,45 @@ impl ConnectionFactory<AnyConnection> for AnyClient {
     }
 }
 
+/// Create the connections of an [`AnyRedisPool`] until the pool is shut
+/// down, `redis_pool` has no way to close a pool
+#[derive(Clone)]
+pub struct PoolFactory {
+    client: AnyClient,
+    closed: Arc<AtomicBool>,
+}
 
+impl PoolFactory {
+    #[must_use]
+    pub fn new(client: AnyClient) -> Self {
+        Self {
+            client,
+            closed: Arc::default(),
+        }
+    }
+
+    /// The client creating the connections
+    #[must_use]
+    pub fn client(&self) -> &AnyClient {
+        &self.client
+    }
+}
 
 #[async_trait::async_trait]
-impl ComponentProvider for RedisPool<AnyClient, AnyConnection> {
+impl ConnectionFactory<AnyConnection> for PoolFactory {
+    async fn create(&self) -> RedisResult<AnyConnection> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(RedisError::from((
+                ErrorKind::ClientError,
+                "the redis pool is shut down",
+            )));
+        }
+        self.client.get_connection().await
+    }
+}
+
+#[async_trait::async_trait]
+impl ComponentProvider for RedisPool<PoolFactory, AnyConnection> {
     type Error = RedisError;
 
     type Config = Config;
@@ -127,13 +564,13 @@ impl ComponentProvider for RedisPool<AnyClient, AnyConnection> {
 
     async fn create(
         config: Self::Config,
-        _: &mut crate::component::ComponentRegister,
+        _: &crate::component::ComponentRegister,
     ) -> Result<Self, Self::Error> {
         tracing::debug!("Creating RedisPool");
 
         let client = AnyClient::new(&config)?;
         let pool = RedisPool::new(
-            client,
+            PoolFactory::new(client),
             config
                 .pool_size
                 .unwrap_or(redis_pool::pool::DEFAULT_POOL_SIZE),
@@ -141,4 +578,94 @@ impl ComponentProvider for RedisPool<AnyClient, AnyConnection> {
         );
         Ok(pool)
     }
+
+    async fn health(&self) -> HealthStatus {
+        let result: Result<(), crate::error::Error> = async {
+            let mut conn = self.acquire().await?;
+            redis::cmd("PING").query_async::<()>(&mut *conn).await?;
+            Ok(())
+        }
+        .await;
+        result.into()
+    }
+
+    /// Close the idle connections and fail the later acquisitions. The
+    /// connections in use are closed when they are released.
+    async fn shutdown(&self) {
+        self.factory().closed.store(true, Ordering::Release);
+
+        // the pool hands out its idle connections before asking the closed
+        // factory for a new one
+        let drain = async {
+            let mut closed = 0;
+            while let Ok(conn) = self.acquire().await {
+                drop(conn.detach());
+                closed += 1;
+            }
+            closed
+        };
+        match tokio::time::timeout(SHUTDOWN_TIMEOUT, drain).await {
+            Ok(closed) => tracing::debug!(connections = closed, "redis connections closed"),
+            Err(_) => tracing::warn!("timed out closing the redis connections"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(toml: &str) -> RedisResult<AnyClient> {
+        AnyClient::new(&crate::config::Config::parse_toml(toml))
+    }
+
+    #[tokio::test]
+    async fn shutdown_closes_the_pool() {
+        let client = client(
+            r#"
+            connection = { type = "single", url = "redis://127.0.0.1:1" }
+            "#,
+        )
+        .unwrap();
+        let pool = AnyRedisPool::new(PoolFactory::new(client), 4, None);
+
+        pool.shutdown().await;
+        let err = pool.acquire().await.err().unwrap();
+        assert!(err.to_string().contains("shut down"), "{err}");
+    }
+
+    #[test]
+    fn sentinel_with_tls_and_credentials() {
+        let client = client(
+            r#"
+            password = "master"
+            db = 2
+            tls = { insecure = true }
+
+            [connection]
+            type = "sentinel"
+            master_name = "mymaster"
+            urls = ["redis://127.0.0.1:26379"]
+            sentinel_password = "sentinel"
+            "#,
+        );
+        assert!(matches!(client, Ok(AnyClient::Sentinel(..))));
+    }
+
+    #[test]
+    fn sentinel_rejects_custom_certificates() {
+        let err = client(
+            r#"
+            tls = { ca_cert = "ca.pem" }
+
+            [connection]
+            type = "sentinel"
+            master_name = "mymaster"
+            urls = ["redis://127.0.0.1:26379"]
+            "#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidClientConfig);
+    }
 }
@@ -0,0 +1,175 @@
+//! 基于 `DbConn` 组件的 SQL 会话存储，支持 Postgres、MySQL 和 Sqlite
+
+use axum_session::{DatabaseError, DatabasePool};
+use regex::Regex;
+use sea_orm::{ConnectionTrait, DatabaseBackend, DbConn, DbErr, Statement, Value};
+use std::sync::OnceLock;
+
+use super::unix_now;
+
+static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+
+/// Store the sessions in the `table_name` table, created on start when it
+/// does not exist.
+#[derive(Debug, Clone)]
+pub struct SessionDbPool {
+    db: DbConn,
+}
+
+impl From<DbConn> for SessionDbPool {
+    fn from(db: DbConn) -> Self {
+        Self { db }
+    }
+}
+
+impl SessionDbPool {
+    /// Build a statement from SQL written with Postgres placeholders (`$1`),
+    /// rewritten to `?` for the other backends.
+    fn statement(&self, sql: &str, values: Vec<Value>) -> Statement {
+        let backend = self.db.get_database_backend();
+        let sql = match backend {
+            DatabaseBackend::Postgres => sql.to_string(),
+            DatabaseBackend::MySql | DatabaseBackend::Sqlite => PLACEHOLDER
+                .get_or_init(|| Regex::new(r"\$\d+").unwrap())
+                .replace_all(sql, "?")
+                .into_owned(),
+        };
+        Statement::from_sql_and_values(backend, sql, values)
+    }
+
+    async fn execute(&self, sql: &str, values: Vec<Value>) -> Result<(), DbErr> {
+        self.db.execute(self.statement(sql, values)).await?;
+        Ok(())
+    }
+
+    async fn query_ids(&self, sql: &str, values: Vec<Value>) -> Result<Vec<String>, DbErr> {
+        self.db
+            .query_all(self.statement(sql, values))
+            .await?
+            .iter()
+            .map(|row| row.try_get::<String>("", "id"))
+            .collect()
+    }
+
+    async fn query_count(&self, sql: &str, values: Vec<Value>) -> Result<i64, DbErr> {
+        match self.db.query_one(self.statement(sql, values)).await? {
+            Some(row) => row.try_get::<i64>("", "count"),
+            None => Ok(0),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl DatabasePool for SessionDbPool {
+    async fn initiate(&self, table_name: &str) -> Result<(), DatabaseError> {
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {table_name} (
+                id VARCHAR(128) NOT NULL PRIMARY KEY,
+                expires BIGINT NULL,
+                session TEXT NOT NULL
+            )"
+        );
+        self.execute(&sql, vec![])
+            .await
+            .map_err(|err| DatabaseError::GenericCreateError(err.to_string()))
+    }
+
+    async fn count(&self, table_name: &str) -> Result<i64, DatabaseError> {
+        let sql = format!("SELECT COUNT(*) AS count FROM {table_name}");
+        self.query_count(&sql, vec![])
+            .await
+            .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))
+    }
+
+    async fn store(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        let sql = match self.db.get_database_backend() {
+            DatabaseBackend::MySql => format!(
+                "INSERT INTO {table_name} (id, session, expires) VALUES ($1, $2, $3)
+                ON DUPLICATE KEY UPDATE session = VALUES(session), expires = VALUES(expires)"
+            ),
+            DatabaseBackend::Postgres | DatabaseBackend::Sqlite => format!(
+                "INSERT INTO {table_name} (id, session, expires) VALUES ($1, $2, $3)
+                ON CONFLICT (id) DO UPDATE SET session = EXCLUDED.session, expires = EXCLUDED.expires"
+            ),
+        };
+        self.execute(&sql, vec![id.into(), session.into(), expires.into()])
+            .await
+            .map_err(|err| DatabaseError::GenericInsertError(err.to_string()))
+    }
+
+    async fn load(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError> {
+        let sql = format!(
+            "SELECT session FROM {table_name} WHERE id = $1 AND (expires IS NULL OR expires >= $2)"
+        );
+        let row = self
+            .db
+            .query_one(self.statement(&sql, vec![id.into(), unix_now().into()]))
+            .await
+            .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))?;
+
+        row.map(|row| row.try_get::<String>("", "session"))
+            .transpose()
+            .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))
+    }
+
+    async fn delete_one_by_id(&self, id: &str, table_name: &str) -> Result<(), DatabaseError> {
+        let sql = format!("DELETE FROM {table_name} WHERE id = $1");
+        self.execute(&sql, vec![id.into()])
+            .await
+            .map_err(|err| DatabaseError::GenericDeleteError(err.to_string()))
+    }
+
+    async fn exists(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        let sql = format!(
+            "SELECT COUNT(*) AS count FROM {table_name} WHERE id = $1 AND (expires IS NULL OR expires >= $2)"
+        );
+        self.query_count(&sql, vec![id.into(), unix_now().into()])
+            .await
+            .map(|count| count > 0)
+            .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))
+    }
+
+    async fn delete_by_expiry(&self, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        let now = unix_now();
+
+        let ids = self
+            .query_ids(
+                &format!("SELECT id FROM {table_name} WHERE expires < $1"),
+                vec![now.into()],
+            )
+            .await
+            .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))?;
+
+        self.execute(
+            &format!("DELETE FROM {table_name} WHERE expires < $1"),
+            vec![now.into()],
+        )
+        .await
+        .map_err(|err| DatabaseError::GenericDeleteError(err.to_string()))?;
+
+        Ok(ids)
+    }
+
+    async fn delete_all(&self, table_name: &str) -> Result<(), DatabaseError> {
+        self.execute(&format!("DELETE FROM {table_name}"), vec![])
+            .await
+            .map_err(|err| DatabaseError::GenericDeleteError(err.to_string()))
+    }
+
+    async fn get_ids(&self, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        let sql = format!("SELECT id FROM {table_name} WHERE expires IS NULL OR expires >= $1");
+        self.query_ids(&sql, vec![unix_now().into()])
+            .await
+            .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))
+    }
+
+    fn auto_handles_expiry(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,102 @@
+//! 纯内存的会话存储，用于测试和单节点工具，进程退出后会话丢失
+
+use axum_session::{DatabaseError, DatabasePool};
+use dashmap::DashMap;
+use std::sync::Arc;
+
+use super::unix_now;
+
+/// Keep the sessions in a map of the process.
+#[derive(Debug, Clone, Default)]
+pub struct SessionMemoryPool {
+    /// `(table, id)` to `(session, expires)`
+    sessions: Arc<DashMap<(String, String), (String, i64)>>,
+}
+
+impl SessionMemoryPool {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn key(id: &str, table_name: &str) -> (String, String) {
+        (table_name.to_string(), id.to_string())
+    }
+
+    fn live_ids(&self, table_name: &str) -> Vec<String> {
+        let now = unix_now();
+        self.sessions
+            .iter()
+            .filter(|entry| entry.key().0 == table_name && entry.value().1 >= now)
+            .map(|entry| entry.key().1.clone())
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl DatabasePool for SessionMemoryPool {
+    async fn initiate(&self, _table_name: &str) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+
+    async fn count(&self, table_name: &str) -> Result<i64, DatabaseError> {
+        Ok(self.live_ids(table_name).len() as i64)
+    }
+
+    async fn store(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        self.sessions
+            .insert(Self::key(id, table_name), (session.to_string(), expires));
+        Ok(())
+    }
+
+    async fn load(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError> {
+        let now = unix_now();
+        Ok(self
+            .sessions
+            .get(&Self::key(id, table_name))
+            .filter(|entry| entry.value().1 >= now)
+            .map(|entry| entry.value().0.clone()))
+    }
+
+    async fn delete_one_by_id(&self, id: &str, table_name: &str) -> Result<(), DatabaseError> {
+        self.sessions.remove(&Self::key(id, table_name));
+        Ok(())
+    }
+
+    async fn exists(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        Ok(self.load(id, table_name).await?.is_some())
+    }
+
+    async fn delete_by_expiry(&self, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        let now = unix_now();
+        let mut expired = vec![];
+        self.sessions.retain(|(table, id), (_, expires)| {
+            if table == table_name && *expires < now {
+                expired.push(id.clone());
+                false
+            } else {
+                true
+            }
+        });
+        Ok(expired)
+    }
+
+    async fn delete_all(&self, table_name: &str) -> Result<(), DatabaseError> {
+        self.sessions.retain(|(table, _), _| table != table_name);
+        Ok(())
+    }
+
+    async fn get_ids(&self, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        Ok(self.live_ids(table_name))
+    }
+
+    fn auto_handles_expiry(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,256 @@
+#[cfg(feature = "with-db")]
+use crate::component::database::DB;
+use crate::component::redis::{AnyClient, AnyRedisPool};
+use crate::component::{ComponentProvider, ComponentRegister};
+use crate::error::{Error, Result};
+use axum_session::{SameSite as CookieSameSite, SessionAnyPool, SessionConfig};
+pub use axum_session::{SessionAnySessionStore, SessionLayer};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "with-db")]
+pub mod database;
+pub mod memory;
+pub mod redis;
+
+/// The `[session]` section, the options not set keep the defaults of
+/// [`SessionConfig`]. Durations are in seconds.
+#[derive(Serialize, Deserialize)]
+pub struct Config {
+    /// Where the sessions are stored
+    #[serde(default)]
+    pub backend: Backend,
+
+    /// Instance of the `DbConn` component used by the `database` backend,
+    /// the default instance when not set
+    pub database: Option<String>,
+
+    /// Idle lifetime, the session expires when not used for this long
+    #[serde(default)]
+    pub life_time: Option<u64>,
+
+    /// Maximum lifetime of a session in the store, used by "remember me"
+    /// sessions
+    pub max_life_time: Option<u64>,
+
+    /// Name of the session cookie
+    pub cookie_name: Option<String>,
+
+    /// Domain of the session cookie, the host of the request when not set
+    pub cookie_domain: Option<String>,
+
+    /// Path of the session cookie
+    pub cookie_path: Option<String>,
+
+    /// `Max-Age` of the session cookie, should not be shorter than
+    /// `max_life_time`
+    pub cookie_max_age: Option<u64>,
+
+    /// Only send the cookie over HTTPS
+    pub secure: Option<bool>,
+
+    /// Hide the cookie from JavaScript
+    pub http_only: Option<bool>,
+
+    /// `SameSite` attribute of the cookie
+    pub same_site: Option<SameSite>,
+
+    /// Table name of the database store, key prefix of the Redis store.
+    /// Must not be empty, the Redis store would share the keys of the other
+    /// components
+    pub table_name: Option<String>,
+
+    /// How long a session stays cached in memory before reloaded from the
+    /// store
+    pub memory_life_time: Option<u64>,
+
+    /// How often expired sessions are purged from memory
+    pub purge_interval: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The `AnyRedisPool` component
+    #[default]
+    Redis,
+    /// The `DbConn` component, requires the `with-db` feature
+    Database,
+    /// The memory of the process, sessions are lost on restart
+    Memory,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl From<SameSite> for CookieSameSite {
+    fn from(same_site: SameSite) -> Self {
+        match same_site {
+            SameSite::Strict => Self::Strict,
+            SameSite::Lax => Self::Lax,
+            SameSite::None => Self::None,
+        }
+    }
+}
+
+/// Current unix timestamp, compared with the `expires` of the sessions
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+fn duration(key: &str, secs: u64) -> Result<chrono::Duration> {
+    chrono::Duration::from_std(std::time::Duration::from_secs(secs))
+        .map_err(|_| Error::Message(format!("session.{key} is out of range: {secs}")))
+}
+
+impl Config {
+    /// Build the [`SessionConfig`] of the store.
+    ///
+    /// # Errors
+    /// Return an error when a duration is out of range or the table name is
+    /// empty.
+    pub fn session_config(&self) -> Result<SessionConfig> {
+        let mut config = SessionConfig::default();
+
+        if let Some(secs) = self.life_time {
+            config = config.with_lifetime(duration("life_time", secs)?);
+        }
+
+        if let Some(secs) = self.max_life_time {
+            config = config.with_max_lifetime(duration("max_life_time", secs)?);
+        }
+
+        if let Some(name) = &self.cookie_name {
+            config = config.with_session_name(name.clone());
+        }
+
+        if let Some(domain) = &self.cookie_domain {
+            config = config.with_cookie_domain(domain.clone());
+        }
+
+        if let Some(path) = &self.cookie_path {
+            config = config.with_cookie_path(path.clone());
+        }
+
+        if let Some(secs) = self.cookie_max_age {
+            config = config.with_max_age(Some(duration("cookie_max_age", secs)?));
+        }
+
+        if let Some(secure) = self.secure {
+            config = config.with_secure(secure);
+        }
+
+        if let Some(http_only) = self.http_only {
+            config = config.with_http_only(http_only);
+        }
+
+        if let Some(same_site) = self.same_site {
+            config = config.with_cookie_same_site(same_site.into());
+        }
+
+        if let Some(table_name) = &self.table_name {
+            if table_name.is_empty() {
+                return Err(Error::Message(
+                    "session.table_name must not be empty".to_string(),
+                ));
+            }
+            config = config.with_table_name(table_name.clone());
+        }
+
+        if let Some(secs) = self.memory_life_time {
+            config = config.with_memory_lifetime(duration("memory_life_time", secs)?);
+        }
+
+        if let Some(secs) = self.purge_interval {
+            config = config.with_purge_update(duration("purge_interval", secs)?);
+        }
+
+        Ok(config)
+    }
+}
+
+#[async_trait::async_trait]
+impl ComponentProvider for SessionAnySessionStore {
+    type Error = Error;
+
+    type Config = Config;
+
+    fn config_key() -> &'static str {
+        "session"
+    }
+
+    async fn create(config: Self::Config, component_register: &ComponentRegister) -> Result<Self> {
+        let session_config = config.session_config()?;
+
+        let pool = match config.backend {
+            Backend::Redis => redis_session_pool(component_register).await?,
+            #[cfg(feature = "with-db")]
+            Backend::Database => {
+                let db = match &config.database {
+                    Some(name) => component_register.component_named::<DB>(name).await?,
+                    None => component_register.component::<DB>().await?,
+                };
+                SessionAnyPool::new(database::SessionDbPool::from(db))
+            }
+            #[cfg(not(feature = "with-db"))]
+            Backend::Database => {
+                return Err(Error::string(
+                    "session backend `database` requires the `with-db` feature",
+                ))
+            }
+            Backend::Memory => SessionAnyPool::new(memory::SessionMemoryPool::new()),
+        };
+
+        Ok(SessionAnySessionStore::new(Some(pool), session_config).await?)
+    }
+}
+
+async fn redis_session_pool(component_register: &ComponentRegister) -> Result<SessionAnyPool> {
+    let redis_pool = component_register.component::<AnyRedisPool>().await?;
+
+    let pool = match redis_pool.factory().client().clone() {
+        AnyClient::Single(client, _) => {
+            SessionAnyPool::new(axum_session_redispool::SessionRedisPool::from(
+                redis_pool::SingleRedisPool::from(client),
+            ))
+        }
+        AnyClient::Cluster(client) => {
+            SessionAnyPool::new(axum_session_redispool::SessionRedisClusterPool::from(
+                redis_pool::ClusterRedisPool::from(client),
+            ))
+        }
+        // failover needs the reconnecting connections of `AnyRedisPool`
+        AnyClient::Sentinel(..) => {
+            SessionAnyPool::new(redis::SessionAnyRedisPool::from(redis_pool))
+        }
+    };
+
+    Ok(pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> Config {
+        crate::config::Config::from_toml(toml)
+            .get("session")
+            .unwrap()
+    }
+
+    #[test]
+    fn empty_table_name_is_an_error() {
+        assert!(config("[session]\ntable_name = \"\"")
+            .session_config()
+            .is_err());
+        assert!(config("[session]\ntable_name = \"sessions\"")
+            .session_config()
+            .is_ok());
+    }
+}
@@ -0,0 +1,171 @@
+//! 基于 `AnyRedisPool` 组件的会话存储，用于 `axum_session_redispool` 不支持的
+//! 连接方式，例如 Sentinel
+
+use axum_session::{DatabaseError, DatabasePool};
+use redis::AsyncCommands;
+
+use crate::component::redis::{AnyConnection, AnyRedisPool};
+
+/// Store the sessions as `<table_name>:<id>` keys expiring with the sessions,
+/// `session:<id>` when the table name is empty.
+#[derive(Clone)]
+pub struct SessionAnyRedisPool {
+    pool: AnyRedisPool,
+}
+
+impl From<AnyRedisPool> for SessionAnyRedisPool {
+    fn from(pool: AnyRedisPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl std::fmt::Debug for SessionAnyRedisPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionAnyRedisPool").finish()
+    }
+}
+
+/// Key prefix of the sessions without a table name, a pattern matching
+/// every key would delete the keys of the other components sharing the pool
+const DEFAULT_PREFIX: &str = "session";
+
+impl SessionAnyRedisPool {
+    fn key(id: &str, table_name: &str) -> String {
+        let prefix = if table_name.is_empty() {
+            DEFAULT_PREFIX
+        } else {
+            table_name
+        };
+        format!("{prefix}:{id}")
+    }
+
+    async fn acquire(
+        &self,
+    ) -> Result<redis_pool::connection::RedisPoolConnection<AnyConnection>, DatabaseError> {
+        self.pool
+            .acquire()
+            .await
+            .map_err(|err| DatabaseError::GenericAquire(err.to_string()))
+    }
+
+    /// Keys of the table, with `SCAN` which does not block the server
+    async fn scan_keys(&self, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        let pattern = format!("{}*", Self::key("", table_name));
+        let mut conn = self.acquire().await?;
+
+        let mut keys = vec![];
+        let mut cursor: u64 = 0;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .query_async(&mut *conn)
+                .await
+                .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))?;
+
+            keys.extend(batch);
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        Ok(keys)
+    }
+}
+
+#[async_trait::async_trait]
+impl DatabasePool for SessionAnyRedisPool {
+    async fn initiate(&self, _table_name: &str) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+
+    async fn count(&self, table_name: &str) -> Result<i64, DatabaseError> {
+        Ok(self.scan_keys(table_name).await?.len() as i64)
+    }
+
+    async fn store(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        let key = Self::key(id, table_name);
+        let mut conn = self.acquire().await?;
+        redis::pipe()
+            .atomic()
+            .set(&key, session)
+            .ignore()
+            .expire_at(&key, expires)
+            .ignore()
+            .query_async::<()>(&mut *conn)
+            .await
+            .map_err(|err| DatabaseError::GenericInsertError(err.to_string()))
+    }
+
+    async fn load(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError> {
+        let mut conn = self.acquire().await?;
+        conn.get(Self::key(id, table_name))
+            .await
+            .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))
+    }
+
+    async fn delete_one_by_id(&self, id: &str, table_name: &str) -> Result<(), DatabaseError> {
+        let mut conn = self.acquire().await?;
+        conn.del(Self::key(id, table_name))
+            .await
+            .map_err(|err| DatabaseError::GenericDeleteError(err.to_string()))
+    }
+
+    async fn exists(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        let mut conn = self.acquire().await?;
+        conn.exists(Self::key(id, table_name))
+            .await
+            .map_err(|err| DatabaseError::GenericSelectError(err.to_string()))
+    }
+
+    async fn delete_by_expiry(&self, _table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        // the keys expire with the sessions
+        Ok(vec![])
+    }
+
+    async fn delete_all(&self, table_name: &str) -> Result<(), DatabaseError> {
+        let keys = self.scan_keys(table_name).await?;
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self.acquire().await?;
+        conn.del(keys)
+            .await
+            .map_err(|err| DatabaseError::GenericDeleteError(err.to_string()))
+    }
+
+    async fn get_ids(&self, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        let prefix = Self::key("", table_name);
+        Ok(self
+            .scan_keys(table_name)
+            .await?
+            .into_iter()
+            .map(|key| key.strip_prefix(&prefix).unwrap_or(&key).to_string())
+            .collect())
+    }
+
+    fn auto_handles_expiry(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_always_prefixed() {
+        assert_eq!(SessionAnyRedisPool::key("id", "sessions"), "sessions:id");
+        assert_eq!(SessionAnyRedisPool::key("id", ""), "session:id");
+        assert_eq!(SessionAnyRedisPool::key("", ""), "session:");
+    }
+}
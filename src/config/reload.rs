@@ -0,0 +1,124 @@
+//! 配置热加载：轮询配置文件，变化时重新加载并校验
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use super::{config_keys, Config, ConfigLoader, Environment};
+use crate::error::Result;
+
+/// The `[hot_reload]` section, the reload is enabled as soon as the table
+/// is present, unless it sets `enable = false`. Only `start`, `worker` and
+/// `scheduler` watch the config.
+///
+/// The framework only applies the `[log]` filter of a reloaded config. The
+/// components, the middlewares and e.g. a
+/// [`RateLimiter`](crate::component::rate_limit::RateLimiter) keep the boot
+/// config, the ones following the changes subscribe to them, see
+/// [`ComponentRegister::subscribe_config`](crate::component::ComponentRegister::subscribe_config)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadConfig {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+
+    /// How often the config files are checked, in milliseconds
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_enable() -> bool {
+    true
+}
+
+fn default_interval() -> u64 {
+    2000
+}
+
+impl ReloadConfig {
+    /// Read the `[hot_reload]` section, `None` when the reload is disabled.
+    ///
+    /// # Errors
+    /// Return an error when the section is invalid.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        Ok(config
+            .get_optional::<Self>(config_keys::HOT_RELOAD)?
+            .filter(|reload| reload.enable))
+    }
+}
+
+/// Modification time and size of each watched file, `None` when missing
+type Fingerprint = Vec<Option<(SystemTime, u64)>>;
+
+fn fingerprint(files: &[PathBuf]) -> Fingerprint {
+    files
+        .iter()
+        .map(|file| {
+            let metadata = fs::metadata(file).ok()?;
+            Some((metadata.modified().ok()?, metadata.len()))
+        })
+        .collect()
+}
+
+/// Reload the config of `environment` from the folder of `current` whenever
+/// one of its files is created, changed or removed, and `publish` it once
+/// `validate` accepts it. A rejected config is logged and the current one
+/// kept.
+///
+/// The environment variables are read once at boot, changing them needs a
+/// restart, as do the files read by the `read_file` template helper.
+pub(crate) async fn watch<V, P>(
+    current: &Config,
+    environment: Environment,
+    interval: Duration,
+    validate: V,
+    publish: P,
+) where
+    V: Fn(&Config) -> std::result::Result<(), Vec<String>>,
+    P: Fn(Config),
+{
+    let folder = current.folder().to_path_buf();
+    let mut files = current.candidate_files().to_vec();
+    let mut last = fingerprint(&files);
+    if last.iter().all(Option::is_none) {
+        tracing::warn!(
+            folder = %folder.display(),
+            "none of the config files exist, the config will not be reloaded"
+        );
+    }
+
+    let mut ticks = tokio::time::interval(interval.max(Duration::from_millis(100)));
+    loop {
+        ticks.tick().await;
+
+        let now = fingerprint(&files);
+        if now == last {
+            continue;
+        }
+        last = now;
+
+        tracing::info!("config files changed, reloading");
+        let config = match ConfigLoader::default().load_folder(&environment, &folder) {
+            Ok(config) => config,
+            Err(err) => {
+                tracing::error!(error = %err, "config reload rejected, keeping the current config");
+                continue;
+            }
+        };
+        if let Err(errors) = validate(&config) {
+            for error in &errors {
+                tracing::error!(error, "invalid reloaded config");
+            }
+            tracing::error!(
+                count = errors.len(),
+                "config reload rejected, keeping the current config"
+            );
+            continue;
+        }
+
+        files = config.candidate_files().to_vec();
+        last = fingerprint(&files);
+        publish(config);
+        tracing::info!("config reloaded");
+    }
+}
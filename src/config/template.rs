@@ -0,0 +1,245 @@
+//! 配置文件的 Tera 模板渲染，提供读取环境变量、密钥文件等辅助函数
+
+use base64::Engine;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tera::{Context, Tera};
+
+use crate::error::{Error, Result};
+
+/// A helper call which failed, located in the template by its helper name
+/// and argument
+type Failures = Arc<Mutex<Vec<(&'static str, Option<String>)>>>;
+
+/// Render the config file `path` of content `template`. Besides the
+/// environment variables, kept as variables of the context, templates may
+/// call:
+///
+/// - `get_env(name="PORT", default="3000")`, the default is required when
+///   the variable may be unset
+/// - `required_env(name="DATABASE_URL")`, fail when the variable is unset
+/// - `read_file(path="/run/secrets/db_password")`, relative paths are
+///   resolved from the folder of the config file, trailing whitespace is
+///   trimmed
+/// - the `base64_decode` filter, e.g. `{{ get_env(name="KEY") | base64_decode }}`
+///
+/// # Errors
+/// Return an error with the file and, when found, the line of the failing
+/// call.
+pub(crate) fn render(path: &Path, template: &str) -> Result<String> {
+    let mut context = Context::new();
+    for (key, val) in env::vars() {
+        context.insert(key, &val);
+    }
+
+    let failures = Failures::default();
+    let folder = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let mut tera = Tera::default();
+    tera.autoescape_on(vec![]);
+    tera.register_function("get_env", get_env(failures.clone()));
+    tera.register_function("required_env", required_env(failures.clone()));
+    tera.register_function("read_file", read_file(folder, failures.clone()));
+    tera.register_filter("base64_decode", base64_decode(failures.clone()));
+
+    let name = path.display().to_string();
+    tera.add_raw_template(&name, template)
+        .and_then(|()| tera.render(&name, &context))
+        .map_err(|err| {
+            let failure = failures
+                .lock()
+                .expect("template failures lock poisoned")
+                .pop();
+            let line = failure.and_then(|(helper, arg)| locate(template, helper, arg.as_deref()));
+            let location = line.map_or_else(|| name.clone(), |line| format!("{name}:{line}"));
+            Error::Message(format!("{location}: {}", error_chain(&err)))
+        })
+}
+
+/// The 1-based line of the first call of `helper` with `arg`.
+fn locate(template: &str, helper: &str, arg: Option<&str>) -> Option<usize> {
+    template
+        .lines()
+        .position(|line| {
+            line.contains(helper)
+                && arg.is_none_or(|arg| {
+                    line.contains(&format!("\"{arg}\"")) || line.contains(&format!("'{arg}'"))
+                })
+        })
+        .map(|index| index + 1)
+}
+
+/// The messages of `err` and its sources, Tera puts the cause in the sources.
+fn error_chain(err: &tera::Error) -> String {
+    let mut messages = vec![err.to_string()];
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        messages.push(err.to_string());
+        source = err.source();
+    }
+    messages.join(": ")
+}
+
+fn string_arg<'a>(
+    args: &'a HashMap<String, Value>,
+    helper: &str,
+    name: &str,
+) -> tera::Result<&'a str> {
+    args.get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| tera::Error::msg(format!("`{helper}` requires a string `{name}` argument")))
+}
+
+fn get_env(failures: Failures) -> impl tera::Function {
+    move |args: &HashMap<String, Value>| {
+        let name = string_arg(args, "get_env", "name")?;
+        match (env::var(name), args.get("default")) {
+            (Ok(value), _) => Ok(Value::String(value)),
+            (Err(_), Some(default)) => Ok(default.clone()),
+            (Err(_), None) => {
+                failures
+                    .lock()
+                    .expect("template failures lock poisoned")
+                    .push(("get_env", Some(name.to_string())));
+                Err(tera::Error::msg(format!(
+                    "environment variable `{name}` is not set, give a `default` or set it"
+                )))
+            }
+        }
+    }
+}
+
+fn required_env(failures: Failures) -> impl tera::Function {
+    move |args: &HashMap<String, Value>| {
+        let name = string_arg(args, "required_env", "name")?;
+        env::var(name).map(Value::String).map_err(|_| {
+            failures
+                .lock()
+                .expect("template failures lock poisoned")
+                .push(("required_env", Some(name.to_string())));
+            tera::Error::msg(format!("environment variable `{name}` is required"))
+        })
+    }
+}
+
+fn read_file(folder: PathBuf, failures: Failures) -> impl tera::Function {
+    move |args: &HashMap<String, Value>| {
+        let path = string_arg(args, "read_file", "path")?;
+        fs::read_to_string(folder.join(path))
+            .map(|content| Value::String(content.trim_end().to_string()))
+            .map_err(|err| {
+                failures
+                    .lock()
+                    .expect("template failures lock poisoned")
+                    .push(("read_file", Some(path.to_string())));
+                tera::Error::msg(format!("failed to read `{path}`: {err}"))
+            })
+    }
+}
+
+fn base64_decode(failures: Failures) -> impl tera::Filter {
+    move |value: &Value, _: &HashMap<String, Value>| {
+        let decoded = value
+            .as_str()
+            .ok_or_else(|| "expected a string".to_string())
+            .and_then(|s| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(s.trim())
+                    .map_err(|err| err.to_string())
+            })
+            .and_then(|bytes| String::from_utf8(bytes).map_err(|err| err.to_string()));
+
+        decoded.map(Value::String).map_err(|err| {
+            failures
+                .lock()
+                .expect("template failures lock poisoned")
+                .push(("base64_decode", None));
+            tera::Error::msg(format!("`base64_decode` failed: {err}"))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_str(template: &str) -> Result<String> {
+        render(Path::new("config/test.toml"), template)
+    }
+
+    #[test]
+    fn env_helpers() {
+        env::set_var("TEMPLATE_TEST_PORT", "8080");
+        assert_eq!(
+            render_str(
+                "a = {{ get_env(name=\"TEMPLATE_TEST_PORT\") }}\n\
+                 b = {{ get_env(name=\"TEMPLATE_TEST_UNSET\", default=\"3000\") }}\n\
+                 c = {{ required_env(name=\"TEMPLATE_TEST_PORT\") }}\n\
+                 d = {{ TEMPLATE_TEST_PORT }}"
+            )
+            .unwrap(),
+            "a = 8080\nb = 3000\nc = 8080\nd = 8080"
+        );
+    }
+
+    #[test]
+    fn missing_env_is_located() {
+        let err = render_str("a = 1\nb = {{ get_env(name=\"TEMPLATE_TEST_UNSET\") }}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("config/test.toml:2: "), "{err}");
+        assert!(err.contains("`TEMPLATE_TEST_UNSET` is not set"), "{err}");
+
+        let err = render_str("a = {{ required_env(name='TEMPLATE_TEST_UNSET') }}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("config/test.toml:1: "), "{err}");
+        assert!(err.contains("`TEMPLATE_TEST_UNSET` is required"), "{err}");
+    }
+
+    #[test]
+    fn read_file_from_the_config_folder() {
+        let folder = env::temp_dir().join(format!("panshi-template-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("secret"), "s3cret\n").unwrap();
+        let path = folder.join("test.toml");
+
+        let rendered = render(&path, "password = \"{{ read_file(path=\"secret\") }}\"");
+        let missing = render(&path, "a = 1\n\nb = {{ read_file(path=\"missing\") }}");
+        fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(rendered.unwrap(), "password = \"s3cret\"");
+        let err = missing.unwrap_err().to_string();
+        assert!(err.starts_with(&format!("{}:3: ", path.display())), "{err}");
+    }
+
+    #[test]
+    fn base64_decode_filter() {
+        assert_eq!(
+            render_str("{{ \"aGVsbG8=\" | base64_decode }}").unwrap(),
+            "hello"
+        );
+
+        let err = render_str("a = 1\nb = {{ \"%%\" | base64_decode }}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("config/test.toml:2: "), "{err}");
+    }
+
+    #[test]
+    fn locate_helper_calls() {
+        let template = "a = {{ get_env(name=\"A\", default=1) }}\n\
+                        b = {{ get_env(name='B') }}\n\
+                        c = {{ x | base64_decode }}";
+        assert_eq!(locate(template, "get_env", Some("A")), Some(1));
+        assert_eq!(locate(template, "get_env", Some("B")), Some(2));
+        assert_eq!(locate(template, "get_env", None), Some(1));
+        assert_eq!(locate(template, "base64_decode", None), Some(3));
+        assert_eq!(locate(template, "get_env", Some("C")), None);
+        assert_eq!(locate(template, "read_file", None), None);
+    }
+}
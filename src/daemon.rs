@@ -0,0 +1,218 @@
+//! 守护进程相关，用于在没有 systemd 的环境下将应用脱离终端运行
+
+use daemonize::Daemonize;
+use serde::Deserialize;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::config::{config_keys, Config};
+use crate::error::{Error, Result};
+
+/// How long `stop` waits for the process to exit after sending `SIGTERM`.
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DaemonConfig {
+    /// Path of the pid file, relative paths are resolved against the current
+    /// directory
+    #[serde(default = "default_pid_file")]
+    pub pid_file: PathBuf,
+
+    /// File to redirect stdout to, discarded when not set
+    pub stdout: Option<PathBuf>,
+
+    /// File to redirect stderr to, discarded when not set
+    pub stderr: Option<PathBuf>,
+
+    /// Working directory of the daemon, defaults to the current directory
+    pub working_directory: Option<PathBuf>,
+
+    /// User to run the daemon as
+    pub user: Option<String>,
+
+    /// Group to run the daemon as
+    pub group: Option<String>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            pid_file: default_pid_file(),
+            stdout: None,
+            stderr: None,
+            working_directory: None,
+            user: None,
+            group: None,
+        }
+    }
+}
+
+fn default_pid_file() -> PathBuf {
+    PathBuf::from("panshi.pid")
+}
+
+impl DaemonConfig {
+    /// Read the `[daemon]` section, falling back to the defaults when absent.
+    ///
+    /// # Errors
+    /// Return an error when the section exists but is invalid.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let config = config
+            .get_optional::<Self>(config_keys::DAEMON)?
+            .unwrap_or_default();
+
+        let cwd = std::env::current_dir()?;
+        Ok(Self {
+            pid_file: cwd.join(config.pid_file),
+            stdout: config.stdout.map(|p| cwd.join(p)),
+            stderr: config.stderr.map(|p| cwd.join(p)),
+            working_directory: Some(
+                config
+                    .working_directory
+                    .map_or(cwd.clone(), |p| cwd.join(p)),
+            ),
+            ..config
+        })
+    }
+}
+
+/// Detach the current process from the terminal.
+///
+/// Must be called before any async runtime is started, the parent process
+/// exits inside this function and only the daemon returns.
+///
+/// # Errors
+/// Return an error when the log files could not be opened or the process
+/// could not be daemonized (e.g. another instance holds the pid file).
+pub fn daemonize(config: &DaemonConfig) -> Result<()> {
+    if let Some(parent) = config.pid_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut daemon = Daemonize::new()
+        .pid_file(&config.pid_file)
+        .chown_pid_file(true);
+
+    if let Some(dir) = &config.working_directory {
+        daemon = daemon.working_directory(dir);
+    }
+
+    if let Some(user) = &config.user {
+        daemon = daemon.user(user.as_str());
+    }
+
+    if let Some(group) = &config.group {
+        daemon = daemon.group(group.as_str());
+    }
+
+    if let Some(stdout) = &config.stdout {
+        daemon = daemon.stdout(open_log_file(stdout)?);
+    }
+
+    if let Some(stderr) = &config.stderr {
+        daemon = daemon.stderr(open_log_file(stderr)?);
+    }
+
+    daemon.start()?;
+
+    Ok(())
+}
+
+/// Send `SIGTERM` to the daemon and wait until it exits.
+///
+/// # Errors
+/// Return an error when the daemon is not running or does not exit in time.
+pub fn stop(config: &DaemonConfig) -> Result<()> {
+    let pid = running_pid(config)?.ok_or_else(|| Error::string("application is not running"))?;
+
+    send_signal(pid, libc::SIGTERM)?;
+    println!("Sent SIGTERM to process {pid}");
+
+    let started = Instant::now();
+    while is_alive(pid) {
+        if started.elapsed() > STOP_TIMEOUT {
+            return Err(Error::Message(format!(
+                "process {pid} did not exit within {} seconds",
+                STOP_TIMEOUT.as_secs()
+            )));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    remove_pid_file(config);
+    println!("Application stopped");
+
+    Ok(())
+}
+
+/// Print whether the daemon is running.
+///
+/// # Errors
+/// Return an error when the daemon is not running, so the exit code can be
+/// used by scripts.
+pub fn status(config: &DaemonConfig) -> Result<()> {
+    match running_pid(config)? {
+        Some(pid) => {
+            println!("Application is running (pid {pid})");
+            Ok(())
+        }
+        None => Err(Error::string("application is not running")),
+    }
+}
+
+/// Remove the pid file, called by the daemon itself after a graceful shutdown.
+pub fn remove_pid_file(config: &DaemonConfig) {
+    match fs::remove_file(&config.pid_file) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            tracing::warn!(pid_file = ?config.pid_file, error = %err, "failed to remove pid file");
+        }
+        _ => {}
+    }
+}
+
+/// Read the pid file and return the pid if the process is still alive. A
+/// stale pid file is removed.
+fn running_pid(config: &DaemonConfig) -> Result<Option<libc::pid_t>> {
+    if !config.pid_file.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(&config.pid_file)?;
+    let pid = content.trim().parse::<libc::pid_t>().map_err(|_| {
+        Error::Message(format!(
+            "invalid pid file `{}`: {content:?}",
+            config.pid_file.display()
+        ))
+    })?;
+
+    if is_alive(pid) {
+        Ok(Some(pid))
+    } else {
+        remove_pid_file(config);
+        Ok(None)
+    }
+}
+
+fn is_alive(pid: libc::pid_t) -> bool {
+    // SAFETY: signal 0 performs the permission and existence checks only
+    let ret = unsafe { libc::kill(pid, 0) };
+    ret == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+fn send_signal(pid: libc::pid_t, signal: libc::c_int) -> Result<()> {
+    // SAFETY: `kill` has no memory safety requirements
+    if unsafe { libc::kill(pid, signal) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().into())
+    }
+}
+
+fn open_log_file(path: &Path) -> Result<fs::File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    Ok(OpenOptions::new().create(true).append(true).open(path)?)
+}
@@ -22,6 +22,7 @@ pub enum Error {
     #[error(transparent)]
     RedisError(#[from] redis::RedisError),
 
+    #[cfg(feature = "with-db")]
     #[error(transparent)]
     DB(#[from] sea_orm::DbErr),
 
@@ -34,12 +35,22 @@ pub enum Error {
     #[error(transparent)]
     Tera(#[from] tera::Error),
 
+    #[error(transparent)]
+    Daemonize(#[from] daemonize::Error),
+
     #[error(transparent)]
     Any(#[from] Box<dyn std::error::Error + Send + Sync>),
 
     #[error("{0}")]
     Message(String),
 
+    /// Rejected by a rate limit, answered with `429 Too Many Requests`
+    #[error("too many requests, retry after {retry_after:?}")]
+    TooManyRequests { retry_after: std::time::Duration },
+
+    #[error("component dependency cycle detected: {}", .0.join(" -> "))]
+    ComponentCycle(Vec<String>),
+
     #[error("")]
     CustomError(axum::http::StatusCode, ErrorDetail),
 
@@ -107,4 +118,4 @@ impl Error {
             },
         }
     }
-}
\ No newline at end of file
+}
@@ -1,11 +1,23 @@
+use crate::app::{create_app, watch_config, AppContext, AppTrait as BaseAppTrait};
+use crate::component::session::{self, SessionAnySessionStore};
+use crate::component::ComponentProvider;
+use crate::config::{config_keys, Config, Environment};
+use crate::error::Result;
+use crate::http::middleware;
+use crate::http::route::AppRoutes;
+use crate::scheduler;
 use serde::Deserialize;
+use std::net::SocketAddr;
 use tokio::signal;
-use crate::app::{AppContext, AppTrait as BaseAppTrait};
-use crate::http::route::AppRoutes;
+use tokio::sync::oneshot;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ServerConfig {
     pub listen: String,
+
+    /// Built-in middlewares, see [`middleware::Config`]
+    #[serde(default)]
+    pub middlewares: middleware::Config,
 }
 
 #[async_trait::async_trait]
@@ -14,7 +26,88 @@ pub trait AppTrait: BaseAppTrait {
     async fn routes(app: AppContext<Self>) -> crate::error::Result<AppRoutes<Self>>;
 }
 
-async fn shutdown_signal() {
+/// Boot the application and serve it on the address configured in
+/// [`ServerConfig::listen`] until a shutdown signal is received, then shut
+/// the components down. The periodic tasks run alongside when
+/// `scheduler.run_with_server` is set.
+///
+/// # Errors
+/// Return an error when the server config is missing, the application or
+/// the session store fails to initialize or the listen address could not be
+/// bound.
+pub async fn start<T: AppTrait>(config: Config, environment: Environment) -> Result<()> {
+    let server_config: ServerConfig = config.get(config_keys::SERVER)?;
+    let tasks = if scheduler::Config::from_config(&config)?.run_with_server {
+        Some(scheduler::tasks::<T>()?)
+    } else {
+        None
+    };
+
+    let (ctx, routes) = build_routes::<T>(config, environment).await?;
+    let components = ctx.components.clone();
+    // fail at boot on an invalid `[session]`, `to_router` adds the layer of
+    // the store
+    if ctx
+        .config
+        .get_optional::<session::Config>(SessionAnySessionStore::config_key())?
+        .is_some()
+    {
+        components.component::<SessionAnySessionStore>().await?;
+    }
+    let config_watch = watch_config(&ctx)?;
+
+    let (stop_scheduler, scheduler_stopped) = oneshot::channel::<()>();
+    let scheduler = tasks.map(|tasks| {
+        tokio::spawn(scheduler::run(ctx.clone(), tasks, async move {
+            let _ = scheduler_stopped.await;
+        }))
+    });
+    let router = routes.to_router(ctx, axum::Router::new())?;
+
+    let listener = tokio::net::TcpListener::bind(&server_config.listen).await?;
+    tracing::info!(listen = %server_config.listen, "server started");
+
+    // the peer address is used e.g. by the rate limit of `ClientIp`
+    let result = axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await;
+
+    drop(config_watch);
+    drop(stop_scheduler);
+    if let Some(scheduler) = scheduler {
+        match scheduler.await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => tracing::error!(error = %err, "scheduler failed"),
+            Err(err) => tracing::error!(error = %err, "scheduler panicked"),
+        }
+    }
+
+    // in-flight requests are done, release the components they were using
+    components.shutdown().await;
+    tracing::info!("server stopped");
+
+    Ok(result?)
+}
+
+/// Initialize the application and build its routes without serving them.
+///
+/// # Errors
+/// Return an error when the application fails to initialize or to register
+/// its routes.
+pub async fn build_routes<T: AppTrait>(
+    config: Config,
+    environment: Environment,
+) -> Result<(AppContext<T>, AppRoutes<T>)> {
+    let ctx = create_app::<T>(config, environment).await?;
+    let routes = T::routes(ctx.clone()).await?;
+    Ok((ctx, routes))
+}
+
+/// Complete on Ctrl+C or, on Unix, `SIGTERM`.
+pub(crate) async fn shutdown_signal() {
     let ctrl_c = async {
         signal::ctrl_c()
             .await
@@ -36,4 +129,4 @@ async fn shutdown_signal() {
         _ = ctrl_c => {},
         _ = terminate => {},
     }
-}
\ No newline at end of file
+}
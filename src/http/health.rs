@@ -0,0 +1,99 @@
+//! 依赖组件的健康检查，供负载均衡摘除异常节点
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use futures::future::join_all;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::app::AppContext;
+use crate::component::{ComponentRegister, HealthStatus};
+use crate::error::Result;
+use crate::http::app::AppTrait;
+use crate::http::{message, route::Routes};
+
+/// Longest wait for the check of a single component
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Health of all registered components
+#[derive(Debug, Serialize)]
+pub struct Health {
+    pub ok: bool,
+    pub components: BTreeMap<String, ComponentHealth>,
+}
+
+/// Health of a single component
+#[derive(Debug, Serialize)]
+pub struct ComponentHealth {
+    pub ok: bool,
+    /// Round trip of the check in milliseconds
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Check every component created in the register concurrently, see
+/// [`crate::component::ComponentProvider::health`]. A check taking longer
+/// than [`CHECK_TIMEOUT`] reports its component down.
+pub async fn check_components(register: &ComponentRegister) -> Health {
+    let checks = register.created().into_iter().map(|created| async move {
+        let started = Instant::now();
+        let status = tokio::time::timeout(CHECK_TIMEOUT, created.component.health())
+            .await
+            .unwrap_or_else(|_| {
+                HealthStatus::Down(format!("health check timed out after {CHECK_TIMEOUT:?}"))
+            });
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        (
+            created.name.clone(),
+            ComponentHealth {
+                ok: !status.is_down(),
+                latency_ms,
+                error: match status {
+                    HealthStatus::Down(error) => Some(error),
+                    HealthStatus::Up | HealthStatus::Unknown => None,
+                },
+            },
+        )
+    });
+    let components = join_all(checks)
+        .await
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+
+    Health {
+        ok: components.values().all(|c| c.ok),
+        components,
+    }
+}
+
+/// Check the health of the application dependencies, respond with
+/// `503 Service Unavailable` when any of them is down.
+async fn health<T: AppTrait>(State(ctx): State<AppContext<T>>) -> Result<Response> {
+    let health = check_components(&ctx.components).await;
+
+    for (name, component) in &health.components {
+        if let Some(error) = &component.error {
+            tracing::warn!(component = name, error, "health check failed");
+        }
+    }
+
+    let status = if health.ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    Ok((status, message::json(health)?).into_response())
+}
+
+/// Defines and returns the health-related routes.
+pub fn routes<T>() -> Routes<T>
+where
+    T: AppTrait,
+{
+    Routes::new().get("/_health", health::<T>)
+}
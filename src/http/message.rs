@@ -1,12 +1,15 @@
 //! 预定义的一些 HTTP 消息结构体，用于返回 JSON 格式的响应。
 
-use axum::{Json, http::StatusCode};
+pub use crate::error::{ErrorDetail, Result};
+use crate::view::ViewRenderer;
 use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{
+    http::{header, StatusCode},
+    Json,
+};
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-pub use crate::error::{ErrorDetail, Result};
-use crate::view::ViewRenderer;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ResponseMessage<T = ()> {
@@ -42,7 +45,6 @@ pub fn ok<T: Serialize>(data: T) -> Resp<T> {
     Ok(json_response(data))
 }
 
-
 impl IntoResponse for crate::error::Error {
     /// Convert an `Error` into an HTTP response.
     fn into_response(self) -> Response {
@@ -57,6 +59,9 @@ impl IntoResponse for crate::error::Error {
                 "controller_error"
                 );
             }
+            Self::TooManyRequests { retry_after } => {
+                tracing::debug!(?retry_after, "too_many_requests");
+            }
             err => {
                 tracing::error!(
                 error.msg = %err,
@@ -66,6 +71,14 @@ impl IntoResponse for crate::error::Error {
             }
         }
 
+        let retry_after = match &self {
+            Self::TooManyRequests { retry_after } => {
+                // rounded up, the header only takes whole seconds
+                Some(retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0))
+            }
+            _ => None,
+        };
+
         let public_facing_error = match self {
             Self::NotFound => (
                 StatusCode::NOT_FOUND,
@@ -85,6 +98,10 @@ impl IntoResponse for crate::error::Error {
                     ),
                 )
             }
+            Self::TooManyRequests { .. } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorDetail::new("too_many_requests", "Too many requests, retry later"),
+            ),
             Self::CustomError(status_code, data) => (status_code, data),
             Self::WithBacktrace { inner, backtrace } => {
                 println!("\n{}", inner.to_string().red().underline());
@@ -100,11 +117,17 @@ impl IntoResponse for crate::error::Error {
             ),
         };
 
-        (
+        let mut response = (
             public_facing_error.0,
             json_error_response(public_facing_error.1),
         )
-            .into_response()
+            .into_response();
+        if let Some(retry_after) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, header::HeaderValue::from(retry_after));
+        }
+        response
     }
 }
 
@@ -142,9 +165,9 @@ where
 }
 
 mod backtrace {
-    use std::sync::LazyLock;
     use crate::error::{Error, Result};
     use regex::Regex;
+    use std::sync::LazyLock;
     static NAME_BLOCKLIST: LazyLock<Vec<Regex>> = LazyLock::new(|| {
         [
             "^___rust_try",
@@ -171,9 +194,9 @@ mod backtrace {
             "^catch_unwind",
             */
         ]
-            .iter()
-            .map(|s| Regex::new(s).unwrap())
-            .collect::<Vec<_>>()
+        .iter()
+        .map(|s| Regex::new(s).unwrap())
+        .collect::<Vec<_>>()
     });
 
     static FILE_BLOCKLIST: LazyLock<Vec<Regex>> = LazyLock::new(|| {
@@ -185,9 +208,9 @@ mod backtrace {
             "futures-.*$",
             "^/rustc",
         ]
-            .iter()
-            .map(|s| Regex::new(s).unwrap())
-            .collect::<Vec<_>>()
+        .iter()
+        .map(|s| Regex::new(s).unwrap())
+        .collect::<Vec<_>>()
     });
 
     pub fn print_backtrace(bt: &std::backtrace::Backtrace) -> Result<()> {
@@ -197,6 +220,6 @@ mod backtrace {
             &NAME_BLOCKLIST,
             &FILE_BLOCKLIST,
         )
-            .map_err(Error::msg)
+        .map_err(Error::msg)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,41 @@
+use axum::extract::DefaultBodyLimit;
+use byte_unit::Byte;
+use serde::Deserialize;
+
+use super::{default_enable, Middleware};
+use crate::error::{Error, Result};
+
+/// Limit the size of request bodies read by extractors
+#[derive(Debug, Clone, Deserialize)]
+pub struct BodyLimit {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+
+    /// Maximum body size, e.g. `2MB` or `512KiB`
+    #[serde(default = "default_limit")]
+    pub limit: String,
+}
+
+fn default_limit() -> String {
+    "2MB".to_string()
+}
+
+impl Middleware for BodyLimit {
+    fn name(&self) -> &'static str {
+        "body_limit"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enable
+    }
+
+    fn apply(&self, app: axum::Router) -> Result<axum::Router> {
+        let limit = Byte::from_str(&self.limit)
+            .map_err(|err| Error::Message(format!("invalid body limit `{}`: {err}", self.limit)))?
+            .get_bytes();
+        let limit = usize::try_from(limit)
+            .map_err(|_| Error::Message(format!("body limit `{}` is too large", self.limit)))?;
+
+        Ok(app.layer(DefaultBodyLimit::max(limit)))
+    }
+}
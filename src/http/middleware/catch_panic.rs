@@ -0,0 +1,26 @@
+use serde::Deserialize;
+use tower_http::catch_panic::CatchPanicLayer;
+
+use super::{default_enable, Middleware};
+use crate::error::Result;
+
+/// Convert panics in handlers into `500 Internal Server Error` responses
+#[derive(Debug, Clone, Deserialize)]
+pub struct CatchPanic {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+}
+
+impl Middleware for CatchPanic {
+    fn name(&self) -> &'static str {
+        "catch_panic"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enable
+    }
+
+    fn apply(&self, app: axum::Router) -> Result<axum::Router> {
+        Ok(app.layer(CatchPanicLayer::new()))
+    }
+}
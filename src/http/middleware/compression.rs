@@ -0,0 +1,26 @@
+use serde::Deserialize;
+use tower_http::compression::CompressionLayer;
+
+use super::{default_enable, Middleware};
+use crate::error::Result;
+
+/// Compress response bodies according to the `Accept-Encoding` header
+#[derive(Debug, Clone, Deserialize)]
+pub struct Compression {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+}
+
+impl Middleware for Compression {
+    fn name(&self) -> &'static str {
+        "compression"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enable
+    }
+
+    fn apply(&self, app: axum::Router) -> Result<axum::Router> {
+        Ok(app.layer(CompressionLayer::new()))
+    }
+}
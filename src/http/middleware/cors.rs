@@ -0,0 +1,183 @@
+use http::{HeaderName, HeaderValue, Method};
+use serde::Deserialize;
+use std::time::Duration;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+use super::{default_enable, Middleware};
+use crate::error::{Error, Result};
+
+/// Answer CORS preflight requests and add the CORS response headers. `*`
+/// allows any value
+#[derive(Debug, Clone, Deserialize)]
+pub struct Cors {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+
+    /// Allowed origins
+    #[serde(default = "default_any")]
+    pub allow_origins: Vec<String>,
+
+    /// Allowed methods
+    #[serde(default = "default_any")]
+    pub allow_methods: Vec<String>,
+
+    /// Allowed request headers
+    #[serde(default = "default_any")]
+    pub allow_headers: Vec<String>,
+
+    /// Allow credentials, can not be used together with `*`
+    #[serde(default)]
+    pub allow_credentials: bool,
+
+    /// How long the preflight result can be cached, in seconds
+    pub max_age: Option<u64>,
+}
+
+fn default_any() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn is_any(values: &[String]) -> bool {
+    values.iter().any(|v| v == "*")
+}
+
+fn invalid(kind: &str, value: &str) -> Error {
+    Error::Message(format!("invalid cors {kind}: `{value}`"))
+}
+
+impl Cors {
+    /// Build the layer.
+    ///
+    /// # Errors
+    /// Return an error when a value is invalid, or `allow_credentials` is
+    /// combined with `*`.
+    pub(crate) fn layer(&self) -> Result<CorsLayer> {
+        // tower-http panics on this combination when the layer is applied
+        if self.allow_credentials {
+            for (kind, values) in [
+                ("allow_origins", &self.allow_origins),
+                ("allow_methods", &self.allow_methods),
+                ("allow_headers", &self.allow_headers),
+            ] {
+                if is_any(values) {
+                    return Err(Error::Message(format!(
+                        "cors `allow_credentials` requires an explicit `{kind}` list, not `*`"
+                    )));
+                }
+            }
+        }
+
+        let origins = if is_any(&self.allow_origins) {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(
+                self.allow_origins
+                    .iter()
+                    .map(|v| HeaderValue::from_str(v).map_err(|_| invalid("origin", v)))
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        };
+
+        let methods = if is_any(&self.allow_methods) {
+            AllowMethods::any()
+        } else {
+            AllowMethods::list(
+                self.allow_methods
+                    .iter()
+                    .map(|v| Method::from_bytes(v.as_bytes()).map_err(|_| invalid("method", v)))
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        };
+
+        let headers = if is_any(&self.allow_headers) {
+            AllowHeaders::any()
+        } else {
+            AllowHeaders::list(
+                self.allow_headers
+                    .iter()
+                    .map(|v| HeaderName::from_bytes(v.as_bytes()).map_err(|_| invalid("header", v)))
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        };
+
+        let mut layer = CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .allow_credentials(self.allow_credentials);
+
+        if let Some(max_age) = self.max_age {
+            layer = layer.max_age(Duration::from_secs(max_age));
+        }
+
+        Ok(layer)
+    }
+}
+
+impl Middleware for Cors {
+    fn name(&self) -> &'static str {
+        "cors"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enable
+    }
+
+    fn apply(&self, app: axum::Router) -> Result<axum::Router> {
+        Ok(app.layer(self.layer()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cors(toml: &str) -> Cors {
+        crate::config::Config::parse_toml(toml)
+    }
+
+    #[test]
+    fn defaults_allow_any() {
+        let cors = cors("");
+        assert!(cors.enable);
+        assert_eq!(cors.allow_origins, ["*"]);
+        assert!(cors.layer().is_ok());
+    }
+
+    #[test]
+    fn explicit_lists() {
+        let cors = cors(
+            r#"
+            allow_origins = ["https://example.com"]
+            allow_methods = ["GET", "POST"]
+            allow_headers = ["content-type"]
+            allow_credentials = true
+            max_age = 60
+            "#,
+        );
+        assert!(cors.layer().is_ok());
+    }
+
+    #[test]
+    fn credentials_with_wildcard_is_an_error() {
+        let err = cors("allow_credentials = true").layer().unwrap_err();
+        assert!(err.to_string().contains("allow_origins"));
+
+        let err = cors(
+            r#"
+            allow_origins = ["https://example.com"]
+            allow_credentials = true
+            "#,
+        )
+        .layer()
+        .unwrap_err();
+        assert!(err.to_string().contains("allow_methods"));
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert!(cors(r#"allow_origins = ["bad\norigin"]"#).layer().is_err());
+        assert!(cors(r#"allow_methods = ["GE T"]"#).layer().is_err());
+        assert!(cors(r#"allow_headers = ["bad header"]"#).layer().is_err());
+    }
+}
@@ -0,0 +1,78 @@
+//! HTTP 中间件，通过 `[server.middlewares]` 配置启用
+
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::error::Result;
+
+pub mod body_limit;
+pub mod catch_panic;
+pub mod compression;
+pub mod cors;
+pub mod rate_limit;
+pub mod request_id;
+pub mod secure_headers;
+pub mod session;
+pub mod static_files;
+pub mod timeout;
+
+/// A middleware which can be applied to the application router
+pub trait Middleware: Send + Sync {
+    /// Name of the middleware, used in logs
+    fn name(&self) -> &'static str;
+
+    /// Whether the middleware should be applied
+    fn is_enabled(&self) -> bool;
+
+    /// Apply the middleware to the router
+    ///
+    /// # Errors
+    /// Return an error when the middleware config is invalid.
+    fn apply(&self, app: axum::Router) -> Result<axum::Router>;
+}
+
+/// The `[server.middlewares]` section. A middleware is enabled as soon as
+/// its table is present, unless it sets `enable = false`. An unknown
+/// middleware is an error.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub static_files: Option<static_files::StaticFiles>,
+    pub body_limit: Option<body_limit::BodyLimit>,
+    pub compression: Option<compression::Compression>,
+    pub timeout: Option<timeout::Timeout>,
+    pub cors: Option<cors::Cors>,
+    pub secure_headers: Option<secure_headers::SecureHeaders>,
+    pub catch_panic: Option<catch_panic::CatchPanic>,
+    pub request_id: Option<request_id::RequestId>,
+}
+
+impl Config {
+    /// The configured built-in middlewares, from the innermost to the
+    /// outermost layer.
+    #[must_use]
+    pub fn middlewares(&self) -> Vec<Arc<dyn Middleware>> {
+        fn shared<M: Middleware + Clone + 'static>(m: &Option<M>) -> Option<Arc<dyn Middleware>> {
+            m.as_ref()
+                .map(|m| Arc::new(m.clone()) as Arc<dyn Middleware>)
+        }
+
+        [
+            shared(&self.static_files),
+            shared(&self.body_limit),
+            shared(&self.compression),
+            shared(&self.timeout),
+            shared(&self.cors),
+            shared(&self.secure_headers),
+            shared(&self.catch_panic),
+            shared(&self.request_id),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+pub(crate) fn default_enable() -> bool {
+    true
+}
@@ -0,0 +1,165 @@
+use axum::extract::{ConnectInfo, Request};
+use axum::response::{IntoResponse, Response};
+use axum_session::{Session, SessionAnyPool};
+use futures::future::BoxFuture;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+use crate::component::rate_limit::RateLimiter;
+use crate::error::Error;
+
+/// The key a request is counted under by [`RateLimitLayer`], `None` lets the
+/// request through without counting it.
+pub trait RateLimitKey: Clone + Send + Sync + 'static {
+    fn key(&self, request: &Request) -> Option<String>;
+}
+
+impl<F> RateLimitKey for F
+where
+    F: Fn(&Request) -> Option<String> + Clone + Send + Sync + 'static,
+{
+    fn key(&self, request: &Request) -> Option<String> {
+        self(request)
+    }
+}
+
+/// Count requests by the address of the peer. The server must be started
+/// with connect info, as [`crate::http::app::start`] does.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClientIp {
+    /// Use the first address of `X-Forwarded-For` when present. Only enable
+    /// it behind a proxy setting the header, clients can forge it
+    pub trust_forwarded: bool,
+}
+
+impl RateLimitKey for ClientIp {
+    fn key(&self, request: &Request) -> Option<String> {
+        let forwarded = self
+            .trust_forwarded
+            .then(|| request.headers().get("x-forwarded-for")?.to_str().ok())
+            .flatten()
+            .and_then(|value| value.split(',').next())
+            .map(|ip| ip.trim().to_string())
+            .filter(|ip| !ip.is_empty());
+
+        forwarded.or_else(|| {
+            request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+        })
+    }
+}
+
+/// Count requests by session, requires the `[session]` section.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SessionId;
+
+impl RateLimitKey for SessionId {
+    fn key(&self, request: &Request) -> Option<String> {
+        request
+            .extensions()
+            .get::<Session<SessionAnyPool>>()
+            .map(|session| session.get_session_id().inner())
+    }
+}
+
+/// Reject requests over the limit of a [`RateLimiter`] with `429 Too Many
+/// Requests`, e.g.
+///
+/// ```ignore
+/// let limiter = RateLimiter::new(pool, "login", 5, Duration::from_secs(60));
+/// Routes::new()
+///     .post("/login", login)
+///     .layer(RateLimitLayer::new(limiter).key_by(ClientIp::default()))
+/// ```
+///
+/// Requests are let through when Redis fails.
+#[derive(Clone)]
+pub struct RateLimitLayer<K = ClientIp> {
+    limiter: RateLimiter,
+    key: K,
+}
+
+impl RateLimitLayer {
+    /// Limit requests by [`ClientIp`].
+    #[must_use]
+    pub fn new(limiter: RateLimiter) -> Self {
+        Self {
+            limiter,
+            key: ClientIp::default(),
+        }
+    }
+}
+
+impl<K: RateLimitKey> RateLimitLayer<K> {
+    /// Count requests by `key` instead.
+    #[must_use]
+    pub fn key_by<K2: RateLimitKey>(self, key: K2) -> RateLimitLayer<K2> {
+        RateLimitLayer {
+            limiter: self.limiter,
+            key,
+        }
+    }
+}
+
+impl<S, K: RateLimitKey> Layer<S> for RateLimitLayer<K> {
+    type Service = RateLimitService<S, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            limiter: self.limiter.clone(),
+            key: self.key.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S, K> {
+    inner: S,
+    limiter: RateLimiter,
+    key: K,
+}
+
+impl<S, K> Service<Request> for RateLimitService<S, K>
+where
+    S: Service<Request, Error = Infallible> + Clone + Send + 'static,
+    S::Response: IntoResponse,
+    S::Future: Send + 'static,
+    K: RateLimitKey,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Response, Infallible>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // the ready service is the one which must be called
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        let key = self.key.key(&request);
+
+        Box::pin(async move {
+            if let Some(key) = key {
+                match limiter.hit(&key).await {
+                    Ok(limit) if !limit.allowed => {
+                        return Ok(Error::TooManyRequests {
+                            retry_after: limit.retry_after,
+                        }
+                        .into_response());
+                    }
+                    Ok(_) => {}
+                    Err(err) => tracing::warn!(error = %err, "rate limit check failed"),
+                }
+            }
+            inner.call(request).await.map(IntoResponse::into_response)
+        })
+    }
+}
@@ -0,0 +1,29 @@
+use serde::Deserialize;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+
+use super::{default_enable, Middleware};
+use crate::error::Result;
+
+/// Assign an `x-request-id` to requests without one, and copy it to the
+/// response
+#[derive(Debug, Clone, Deserialize)]
+pub struct RequestId {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+}
+
+impl Middleware for RequestId {
+    fn name(&self) -> &'static str {
+        "request_id"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enable
+    }
+
+    fn apply(&self, app: axum::Router) -> Result<axum::Router> {
+        Ok(app
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid)))
+    }
+}
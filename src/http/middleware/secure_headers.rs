@@ -0,0 +1,132 @@
+use http::{HeaderName, HeaderValue};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tower_http::set_header::SetResponseHeaderLayer;
+
+use super::{default_enable, Middleware};
+use crate::error::{Error, Result};
+
+/// Headers set by default, see <https://owasp.org/www-project-secure-headers/>
+const DEFAULT_HEADERS: &[(&str, &str)] = &[
+    ("cross-origin-opener-policy", "same-origin"),
+    ("cross-origin-resource-policy", "same-origin"),
+    ("referrer-policy", "no-referrer"),
+    (
+        "strict-transport-security",
+        "max-age=31536000; includeSubDomains",
+    ),
+    ("x-content-type-options", "nosniff"),
+    ("x-frame-options", "DENY"),
+    ("x-permitted-cross-domain-policies", "none"),
+    ("x-xss-protection", "0"),
+];
+
+/// Add security related headers to responses which do not set them
+#[derive(Debug, Clone, Deserialize)]
+pub struct SecureHeaders {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+
+    /// Override or add headers, an empty value removes a default header
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl SecureHeaders {
+    fn headers(&self) -> Result<Vec<(HeaderName, HeaderValue)>> {
+        let mut headers = DEFAULT_HEADERS
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect::<BTreeMap<_, _>>();
+
+        for (name, value) in &self.headers {
+            headers.insert(name.to_lowercase(), value.clone());
+        }
+
+        headers
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| {
+                let header_name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| Error::Message(format!("invalid header name: `{name}`")))?;
+                let header_value = HeaderValue::from_str(&value)
+                    .map_err(|_| Error::Message(format!("invalid value of header `{name}`")))?;
+                Ok((header_name, header_value))
+            })
+            .collect()
+    }
+}
+
+impl Middleware for SecureHeaders {
+    fn name(&self) -> &'static str {
+        "secure_headers"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enable
+    }
+
+    fn apply(&self, mut app: axum::Router) -> Result<axum::Router> {
+        for (name, value) in self.headers()? {
+            app = app.layer(SetResponseHeaderLayer::if_not_present(name, value));
+        }
+        Ok(app)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secure_headers(toml: &str) -> SecureHeaders {
+        crate::config::Config::parse_toml(toml)
+    }
+
+    fn value<'a>(headers: &'a [(HeaderName, HeaderValue)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.to_str().unwrap())
+    }
+
+    #[test]
+    fn defaults() {
+        let secure_headers = secure_headers("");
+        assert!(secure_headers.enable);
+
+        let headers = secure_headers.headers().unwrap();
+        assert_eq!(headers.len(), DEFAULT_HEADERS.len());
+        assert_eq!(value(&headers, "x-frame-options"), Some("DENY"));
+    }
+
+    #[test]
+    fn override_add_and_remove() {
+        let headers = secure_headers(
+            r#"
+            [headers]
+            X-Frame-Options = "SAMEORIGIN"
+            content-security-policy = "default-src 'self'"
+            x-xss-protection = ""
+            "#,
+        )
+        .headers()
+        .unwrap();
+
+        assert_eq!(value(&headers, "x-frame-options"), Some("SAMEORIGIN"));
+        assert_eq!(
+            value(&headers, "content-security-policy"),
+            Some("default-src 'self'")
+        );
+        assert_eq!(value(&headers, "x-xss-protection"), None);
+        assert_eq!(headers.len(), DEFAULT_HEADERS.len());
+    }
+
+    #[test]
+    fn invalid_header_is_an_error() {
+        let invalid_name = secure_headers("headers = { \"bad header\" = \"1\" }");
+        assert!(invalid_name.headers().is_err());
+
+        let invalid_value = secure_headers("headers = { x-test = \"bad\\nvalue\" }");
+        assert!(invalid_value.headers().is_err());
+    }
+}
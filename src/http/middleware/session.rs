@@ -0,0 +1,88 @@
+use axum_session::SessionLayer;
+use std::sync::Arc;
+
+use super::Middleware;
+use crate::component::session::SessionAnySessionStore;
+use crate::component::ComponentRegister;
+use crate::error::{Error, Result};
+
+/// Install the [`SessionLayer`] of the session store, added when the
+/// `[session]` section is present so handlers can take the
+/// `axum_session::Session` extractor. The store must be created before the
+/// router is built, which `start` does at boot.
+#[derive(Clone)]
+pub struct Session {
+    pub components: Arc<ComponentRegister>,
+}
+
+impl Middleware for Session {
+    fn name(&self) -> &'static str {
+        "session"
+    }
+
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    fn apply(&self, app: axum::Router) -> Result<axum::Router> {
+        let store = self
+            .components
+            .get_created::<SessionAnySessionStore>(None)
+            .ok_or_else(|| {
+                Error::Message("the session store must be created before the router".to_string())
+            })?;
+        Ok(app.layer(SessionLayer::new(store)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::routing::get;
+    use axum_session::SessionAnyPool;
+    use tower::ServiceExt;
+
+    fn app(components: &Arc<ComponentRegister>) -> Result<axum::Router> {
+        let app = axum::Router::new().route(
+            "/",
+            get(
+                |session: axum_session::Session<SessionAnyPool>| async move {
+                    session.set("visited", true);
+                },
+            ),
+        );
+        Session {
+            components: components.clone(),
+        }
+        .apply(app)
+    }
+
+    #[tokio::test]
+    async fn layer_of_the_created_store() {
+        let components = Arc::new(ComponentRegister::new(Config::from_toml(
+            "[session]\nbackend = \"memory\"",
+        )));
+        components
+            .component::<SessionAnySessionStore>()
+            .await
+            .unwrap();
+
+        let response = app(&components)
+            .unwrap()
+            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+    }
+
+    #[test]
+    fn store_not_created_is_an_error() {
+        let components = Arc::new(ComponentRegister::new(Config::from_toml(
+            "[session]\nbackend = \"memory\"",
+        )));
+        assert!(app(&components).is_err());
+    }
+}
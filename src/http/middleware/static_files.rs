@@ -0,0 +1,130 @@
+use axum::extract::Request;
+use axum::response::IntoResponse;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use tower::Service;
+use tower_http::services::{ServeDir, ServeFile};
+
+use super::{default_enable, Middleware};
+use crate::error::{Error, Result};
+
+/// Serve files of a local folder under `uri`
+#[derive(Debug, Clone, Deserialize)]
+pub struct StaticFiles {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+
+    /// Url prefix of the files, `/` serves them for every request not
+    /// matching a route
+    #[serde(default = "default_uri")]
+    pub uri: String,
+
+    /// Folder of the files
+    #[serde(default = "default_path")]
+    pub path: PathBuf,
+
+    /// File served when the requested file does not exist, e.g. the
+    /// `index.html` of a single page application
+    pub fallback: Option<PathBuf>,
+}
+
+fn default_uri() -> String {
+    "/static".to_string()
+}
+
+fn default_path() -> PathBuf {
+    PathBuf::from("assets/static")
+}
+
+impl Middleware for StaticFiles {
+    fn name(&self) -> &'static str {
+        "static_files"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enable
+    }
+
+    fn apply(&self, app: axum::Router) -> Result<axum::Router> {
+        if !self.path.exists() {
+            return Err(Error::Message(format!(
+                "missing static files folder: `{}`",
+                self.path.display()
+            )));
+        }
+
+        let uri = self.uri.as_str();
+        if !uri.starts_with('/') || uri.contains('*') {
+            return Err(Error::Message(format!(
+                "invalid static files uri `{uri}`, it must start with `/` and not contain `*`"
+            )));
+        }
+
+        let serve_dir = ServeDir::new(&self.path);
+        match &self.fallback {
+            Some(fallback) => mount(app, uri, serve_dir.fallback(ServeFile::new(fallback))),
+            None => mount(app, uri, serve_dir),
+        }
+    }
+}
+
+/// Serve `service` under `uri`, or for every request not matching a route
+/// when `uri` is `/`.
+fn mount<S>(app: axum::Router, uri: &str, service: S) -> Result<axum::Router>
+where
+    S: Service<Request, Error = Infallible> + Clone + Send + 'static,
+    S::Response: IntoResponse,
+    S::Future: Send + 'static,
+{
+    if uri == "/" {
+        return Ok(app.fallback_service(service));
+    }
+
+    // axum panics when the uri conflicts with a route, without a way to
+    // check it beforehand
+    panic::catch_unwind(AssertUnwindSafe(|| app.nest_service(uri, service))).map_err(|panic| {
+        let reason = panic
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| panic.downcast_ref::<&str>().copied())
+            .unwrap_or("conflicting routes");
+        Error::Message(format!("invalid static files uri `{uri}`: {reason}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+
+    fn static_files(uri: &str) -> StaticFiles {
+        StaticFiles {
+            enable: true,
+            uri: uri.to_string(),
+            path: PathBuf::from("src"),
+            fallback: None,
+        }
+    }
+
+    #[test]
+    fn nested_and_root_uri() {
+        assert!(static_files("/static").apply(axum::Router::new()).is_ok());
+        assert!(static_files("/").apply(axum::Router::new()).is_ok());
+    }
+
+    #[test]
+    fn invalid_uri_is_an_error() {
+        assert!(static_files("static").apply(axum::Router::new()).is_err());
+        assert!(static_files("/static/*path")
+            .apply(axum::Router::new())
+            .is_err());
+    }
+
+    #[test]
+    fn conflicting_uri_is_an_error() {
+        let app = axum::Router::new().route("/static/*path", get(|| async {}));
+        assert!(static_files("/static").apply(app).is_err());
+    }
+}
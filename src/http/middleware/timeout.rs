@@ -0,0 +1,35 @@
+use serde::Deserialize;
+use std::time::Duration;
+use tower_http::timeout::TimeoutLayer;
+
+use super::{default_enable, Middleware};
+use crate::error::Result;
+
+/// Abort requests running longer than `timeout` with `408 Request Timeout`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Timeout {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+
+    /// Timeout in milliseconds
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+fn default_timeout() -> u64 {
+    5_000
+}
+
+impl Middleware for Timeout {
+    fn name(&self) -> &'static str {
+        "timeout"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enable
+    }
+
+    fn apply(&self, app: axum::Router) -> Result<axum::Router> {
+        Ok(app.layer(TimeoutLayer::new(Duration::from_millis(self.timeout))))
+    }
+}
@@ -1,3 +1,6 @@
-mod message;
-mod app;
-mod route;
\ No newline at end of file
+pub mod app;
+#[cfg(feature = "with-db")]
+pub mod health;
+pub mod message;
+pub mod middleware;
+pub mod route;
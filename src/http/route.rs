@@ -1,19 +1,42 @@
-use axum::routing::MethodRouter;
+use axum::handler::Handler as AxumHandler;
+use axum::http::Method;
+use axum::routing::{self, MethodRouter};
 use axum::{extract::Request, response::IntoResponse, routing::Route};
 use regex::Regex;
+use serde::Serialize;
+use std::any::type_name;
 use std::convert::Infallible;
 use std::fmt;
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 use tower::{Layer, Service};
 
 use crate::app::AppContext;
+use crate::component::session::{self, SessionAnySessionStore};
+use crate::component::ComponentProvider;
+use crate::config::config_keys;
 use crate::error::Result;
-use crate::http::app::AppTrait;
+use crate::http::app::{AppTrait, ServerConfig};
+use crate::http::middleware::session::Session;
+use crate::http::middleware::Middleware;
+
+/// The methods recorded for handlers added with [`Routes::any`].
+pub const ANY_METHODS: [Method; 9] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::PATCH,
+    Method::HEAD,
+    Method::OPTIONS,
+    Method::CONNECT,
+    Method::TRACE,
+];
 
 static DESCRIBE_METHOD_ACTION: OnceLock<Regex> = OnceLock::new();
 
 fn get_describe_method_action() -> &'static Regex {
-    DESCRIBE_METHOD_ACTION.get_or_init(|| Regex::new(r"\b(\w+):\s*BoxedHandler\b").unwrap())
+    DESCRIBE_METHOD_ACTION
+        .get_or_init(|| Regex::new(r"\b(\w+):\s*(?:BoxedHandler|Route)\b").unwrap())
 }
 
 /// Extract the allow list method actions from [`MethodRouter`].
@@ -21,29 +44,44 @@ fn get_describe_method_action() -> &'static Regex {
 /// Currently axum not exposed the action type of the router. for hold extra
 /// information about routers we need to convert the `method` to string and
 /// capture the details
-pub fn method_action<T: AppTrait>(method: &MethodRouter<AppContext<T>>) -> Vec<http::Method> {
-    let method_str = format!("{method:?}");
-
-    get_describe_method_action()
-        .captures(&method_str)
-        .and_then(|captures| captures.get(1).map(|m| m.as_str().to_lowercase()))
-        .and_then(|method_name| match method_name.as_str() {
-            "get" => Some(http::Method::GET),
-            "post" => Some(http::Method::POST),
-            "put" => Some(http::Method::PUT),
-            "delete" => Some(http::Method::DELETE),
-            "head" => Some(http::Method::HEAD),
-            "options" => Some(http::Method::OPTIONS),
-            "connect" => Some(http::Method::CONNECT),
-            "patch" => Some(http::Method::PATCH),
-            "trace" => Some(http::Method::TRACE),
-            _ => {
-                tracing::info!("Unknown method: {}", method_name);
-                None
+#[deprecated(note = "add the handlers with the verb helpers like `Routes::get`")]
+pub fn method_action<T: AppTrait>(method: &MethodRouter<AppContext<T>>) -> Vec<Method> {
+    describe_methods(method)
+}
+
+fn describe_methods<S>(method: &MethodRouter<S>) -> Vec<Method> {
+    let mut actions = vec![];
+    for captures in get_describe_method_action().captures_iter(&format!("{method:?}")) {
+        match &captures[1] {
+            "get" => actions.push(Method::GET),
+            "post" => actions.push(Method::POST),
+            "put" => actions.push(Method::PUT),
+            "delete" => actions.push(Method::DELETE),
+            "head" => actions.push(Method::HEAD),
+            "options" => actions.push(Method::OPTIONS),
+            "connect" => actions.push(Method::CONNECT),
+            "patch" => actions.push(Method::PATCH),
+            "trace" => actions.push(Method::TRACE),
+            // a handler of `any` answers the methods without their own one
+            "fallback" => {
+                for action in ANY_METHODS {
+                    if !actions.contains(&action) {
+                        actions.push(action);
+                    }
+                }
             }
-        })
-        .into_iter()
-        .collect::<Vec<_>>()
+            _ => {}
+        }
+    }
+    actions
+}
+
+/// The type name without module path and generic parameters, e.g.
+/// `TimeoutLayer` for `tower_http::timeout::TimeoutLayer`.
+fn short_type_name<L>() -> String {
+    let name = type_name::<L>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name).to_string()
 }
 
 static NORMALIZE_URL: OnceLock<Regex> = OnceLock::new();
@@ -83,6 +121,11 @@ where
     pub uri: String,
     pub method: axum::routing::MethodRouter<AppContext<T>>,
     pub actions: Vec<axum::http::Method>,
+    /// Type names of the handler functions, only known for handlers added
+    /// with the verb helpers
+    pub handler_names: Vec<String>,
+    /// Names of the layers added with [`Routes::layer`]
+    pub layers: Vec<String>,
 }
 
 impl<T> Routes<T>
@@ -103,17 +146,163 @@ where
         }
     }
 
+    /// Add a raw [`MethodRouter`], merged with the handlers already
+    /// registered on the same `uri`.
+    ///
+    /// The methods it answers are read from its `Debug` output, axum does not
+    /// expose them.
+    #[deprecated(
+        note = "add the handlers with `Routes::on` or the verb helpers like `Routes::get`, which record their methods"
+    )]
+    #[must_use]
+    pub fn add(self, uri: &str, method: axum::routing::MethodRouter<AppContext<T>>) -> Self {
+        self.merge(Handler {
+            uri: uri.to_owned(),
+            actions: describe_methods(&method),
+            method,
+            handler_names: vec![],
+            layers: vec![],
+        })
+    }
+
+    /// Add a handler answering `actions` on `uri`, merged with the handlers
+    /// already registered on the same `uri`.
+    ///
+    /// # Panics
+    /// Panics when one of the `actions` is already handled on `uri`.
     #[must_use]
-    pub fn add(mut self, uri: &str, method: axum::routing::MethodRouter<AppContext<T>>) -> Self {
-        method_action(&method);
-        self.handlers.push(Handler {
+    pub fn on(self, uri: &str, actions: &[Method], method: MethodRouter<AppContext<T>>) -> Self {
+        self.merge_handler(uri, actions, None, method)
+    }
+
+    fn merge_handler(
+        self,
+        uri: &str,
+        actions: &[Method],
+        handler_name: Option<&str>,
+        method: MethodRouter<AppContext<T>>,
+    ) -> Self {
+        self.merge(Handler {
             uri: uri.to_owned(),
-            actions: method_action(&method),
+            actions: actions.to_vec(),
             method,
-        });
+            handler_names: handler_name.map(ToString::to_string).into_iter().collect(),
+            layers: vec![],
+        })
+    }
+
+    /// Merge `new` into the handler of the same `uri`, keeping the methods,
+    /// handler names and layers of both.
+    fn merge(mut self, new: Handler<T>) -> Self {
+        fn extend_unique<V: PartialEq>(values: &mut Vec<V>, new: Vec<V>) {
+            for value in new {
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+        }
+
+        if let Some(handler) = self.handlers.iter_mut().find(|h| h.uri == new.uri) {
+            handler.method = std::mem::take(&mut handler.method).merge(new.method);
+            extend_unique(&mut handler.actions, new.actions);
+            extend_unique(&mut handler.handler_names, new.handler_names);
+            extend_unique(&mut handler.layers, new.layers);
+        } else {
+            self.handlers.push(new);
+        }
         self
     }
 
+    /// Add a `GET` handler on `uri`.
+    #[must_use]
+    pub fn get<H, X>(self, uri: &str, handler: H) -> Self
+    where
+        H: AxumHandler<X, AppContext<T>>,
+        X: 'static,
+    {
+        self.merge_handler(
+            uri,
+            &[Method::GET],
+            Some(type_name::<H>()),
+            routing::get(handler),
+        )
+    }
+
+    /// Add a `POST` handler on `uri`.
+    #[must_use]
+    pub fn post<H, X>(self, uri: &str, handler: H) -> Self
+    where
+        H: AxumHandler<X, AppContext<T>>,
+        X: 'static,
+    {
+        self.merge_handler(
+            uri,
+            &[Method::POST],
+            Some(type_name::<H>()),
+            routing::post(handler),
+        )
+    }
+
+    /// Add a `PUT` handler on `uri`.
+    #[must_use]
+    pub fn put<H, X>(self, uri: &str, handler: H) -> Self
+    where
+        H: AxumHandler<X, AppContext<T>>,
+        X: 'static,
+    {
+        self.merge_handler(
+            uri,
+            &[Method::PUT],
+            Some(type_name::<H>()),
+            routing::put(handler),
+        )
+    }
+
+    /// Add a `DELETE` handler on `uri`.
+    #[must_use]
+    pub fn delete<H, X>(self, uri: &str, handler: H) -> Self
+    where
+        H: AxumHandler<X, AppContext<T>>,
+        X: 'static,
+    {
+        self.merge_handler(
+            uri,
+            &[Method::DELETE],
+            Some(type_name::<H>()),
+            routing::delete(handler),
+        )
+    }
+
+    /// Add a `PATCH` handler on `uri`.
+    #[must_use]
+    pub fn patch<H, X>(self, uri: &str, handler: H) -> Self
+    where
+        H: AxumHandler<X, AppContext<T>>,
+        X: 'static,
+    {
+        self.merge_handler(
+            uri,
+            &[Method::PATCH],
+            Some(type_name::<H>()),
+            routing::patch(handler),
+        )
+    }
+
+    /// Add a handler answering every method on `uri`.
+    #[must_use]
+    pub fn any<H, X>(self, uri: &str, handler: H) -> Self
+    where
+        H: AxumHandler<X, AppContext<T>>,
+        X: 'static,
+    {
+        self.merge_handler(
+            uri,
+            &ANY_METHODS,
+            Some(type_name::<H>()),
+            routing::any(handler),
+        )
+    }
+
     #[must_use]
     pub fn prefix(mut self, uri: &str) -> Self {
         self.prefix = Some(uri.to_owned());
@@ -139,6 +328,13 @@ where
                     uri: handler.uri.clone(),
                     actions: handler.actions.clone(),
                     method: handler.method.clone().layer(layer.clone()),
+                    handler_names: handler.handler_names.clone(),
+                    layers: handler
+                        .layers
+                        .iter()
+                        .cloned()
+                        .chain([short_type_name::<L>()])
+                        .collect(),
                 })
                 .collect(),
         }
@@ -152,6 +348,7 @@ where
 {
     prefix: Option<String>,
     routes: Vec<Routes<T>>,
+    middlewares: Vec<Arc<dyn Middleware>>,
 }
 
 #[derive(Debug)]
@@ -162,6 +359,50 @@ where
     pub uri: String,
     pub actions: Vec<axum::http::Method>,
     pub method: axum::routing::MethodRouter<AppContext<T>>,
+    pub handler_names: Vec<String>,
+    pub layers: Vec<String>,
+}
+
+impl<T> ListRoutes<T>
+where
+    T: AppTrait,
+{
+    /// The methods joined by `,`, `ANY` for handlers added with
+    /// [`Routes::any`] and `?` when unknown.
+    #[must_use]
+    pub fn actions_label(&self) -> String {
+        if self.actions.is_empty() {
+            "?".to_string()
+        } else if ANY_METHODS.iter().all(|m| self.actions.contains(m)) {
+            "ANY".to_string()
+        } else {
+            self.actions
+                .iter()
+                .map(std::string::ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+    }
+
+    /// A serializable description of the route.
+    #[must_use]
+    pub fn describe(&self) -> RouteInfo {
+        RouteInfo {
+            methods: self.actions.iter().map(ToString::to_string).collect(),
+            path: self.uri.clone(),
+            layers: self.layers.clone(),
+            handlers: self.handler_names.clone(),
+        }
+    }
+}
+
+/// Description of a route, as printed by the `routes` command.
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteInfo {
+    pub methods: Vec<String>,
+    pub path: String,
+    pub layers: Vec<String>,
+    pub handlers: Vec<String>,
 }
 
 impl<T> fmt::Display for ListRoutes<T>
@@ -169,14 +410,7 @@ where
     T: AppTrait,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let actions_str = self
-            .actions
-            .iter()
-            .map(std::string::ToString::to_string)
-            .collect::<Vec<_>>()
-            .join(",");
-
-        write!(f, "[{}] {}", actions_str, self.uri)
+        write!(f, "[{}] {}", self.actions_label(), self.uri)
     }
 }
 
@@ -200,6 +434,7 @@ where
         Self {
             prefix: None,
             routes: vec![],
+            middlewares: vec![],
         }
     }
 
@@ -237,6 +472,8 @@ where
                         uri,
                         actions: handler.actions.clone(),
                         method: handler.method.clone(),
+                        handler_names: handler.handler_names.clone(),
+                        layers: handler.layers.clone(),
                     }
                 })
             })
@@ -277,6 +514,57 @@ where
         self
     }
 
+    /// Add a custom middleware, applied inside the built-in ones configured in
+    /// `[server.middlewares]`.
+    #[must_use]
+    pub fn add_middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Names of the middlewares applied by [`AppRoutes::to_router`], from the
+    /// innermost to the outermost layer.
+    ///
+    /// # Errors
+    /// Return an error when the `[server]` config section is invalid.
+    pub fn middleware_names(&self, ctx: &AppContext<T>) -> Result<Vec<&'static str>> {
+        Ok(self
+            .middlewares(ctx)?
+            .iter()
+            .map(|mid| mid.name())
+            .collect())
+    }
+
+    /// The custom middlewares, the session layer when `[session]` is present,
+    /// then the configured built-in ones, from the innermost to the
+    /// outermost layer.
+    fn middlewares(&self, ctx: &AppContext<T>) -> Result<Vec<Arc<dyn Middleware>>> {
+        let configured = ctx
+            .config
+            .get_optional::<ServerConfig>(config_keys::SERVER)?
+            .map(|server| server.middlewares.middlewares())
+            .unwrap_or_default();
+
+        // outside the custom middlewares, they may read the session
+        let session = ctx
+            .config
+            .get_optional::<session::Config>(SessionAnySessionStore::config_key())?
+            .map(|_| {
+                Arc::new(Session {
+                    components: ctx.components.clone(),
+                }) as Arc<dyn Middleware>
+            });
+
+        Ok(self
+            .middlewares
+            .iter()
+            .cloned()
+            .chain(session)
+            .chain(configured)
+            .filter(|mid| mid.is_enabled())
+            .collect())
+    }
+
     /// Add the routes to an existing Axum Router, and set a list of middlewares
     /// that configure in the [`config::Config`]
     ///
@@ -311,20 +599,21 @@ where
             app = app.route(&router.uri, router.method);
         }
 
-        // let middlewares = self.middlewares::<H>(&ctx);
-        // for mid in middlewares {
-        //     app = mid.apply(app)?;
-        //     tracing::info!(name = mid.name(), "+middleware");
-        // }
+        let middlewares = self.middlewares(&ctx)?;
+
+        let mut router = app.with_state(ctx);
+        for mid in middlewares {
+            router = mid.apply(router)?;
+            tracing::info!(name = mid.name(), "+middleware");
+        }
 
-        let router = app.with_state(ctx);
         Ok(router)
     }
 }
 
 pub mod default_routes {
     pub mod ping {
-        use axum::{response::Response, routing::get};
+        use axum::response::Response;
         use serde::Serialize;
 
         use crate::error::Result;
@@ -347,7 +636,78 @@ pub mod default_routes {
         where
             T: AppTrait,
         {
-            Routes::new().add("/_ping", get(ping))
+            Routes::new().get("/_ping", ping)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_raw_method_router() {
+        let method: MethodRouter = routing::get(|| async {}).post(|| async {});
+        assert_eq!(describe_methods(&method), [Method::GET, Method::POST]);
+
+        let layered = method.layer(tower_http::timeout::TimeoutLayer::new(
+            std::time::Duration::from_secs(1),
+        ));
+        assert_eq!(describe_methods(&layered), [Method::GET, Method::POST]);
+
+        let any: MethodRouter = routing::any(|| async {});
+        assert_eq!(describe_methods(&any), ANY_METHODS);
+    }
+
+    #[derive(Clone)]
+    struct App;
+
+    #[async_trait::async_trait]
+    impl crate::app::AppTrait for App {
+        fn app_name() -> &'static str {
+            "test"
         }
+
+        async fn init(_: crate::config::Config, _: crate::config::Environment) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AppTrait for App {
+        async fn routes(_: AppContext<Self>) -> Result<AppRoutes<Self>> {
+            Ok(AppRoutes::empty())
+        }
+    }
+
+    async fn list() {}
+
+    async fn create() {}
+
+    #[test]
+    fn merge_keeps_the_layers() {
+        let routes = Routes::<App>::new()
+            .get("/a", list)
+            .layer(tower_http::timeout::TimeoutLayer::new(
+                std::time::Duration::from_secs(1),
+            ))
+            .post("/a", create);
+
+        assert_eq!(routes.handlers.len(), 1);
+        let handler = &routes.handlers[0];
+        assert_eq!(handler.actions, [Method::GET, Method::POST]);
+        assert_eq!(handler.layers, ["TimeoutLayer"]);
+        assert_eq!(handler.handler_names.len(), 2);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn add_merges_with_the_verb_helpers() {
+        let routes = Routes::<App>::new()
+            .get("/a", || async {})
+            .add("/a", routing::put(|| async {}));
+
+        assert_eq!(routes.handlers.len(), 1);
+        assert_eq!(routes.handlers[0].actions, [Method::GET, Method::PUT]);
     }
 }
@@ -1,8 +1,11 @@
-pub mod http;
-pub mod error;
+pub mod app;
+pub mod cli;
 pub mod component;
 pub mod config;
-mod logger;
-mod app;
-mod view;
-mod cli;
\ No newline at end of file
+pub mod daemon;
+pub mod error;
+pub mod http;
+pub mod logger;
+pub mod scheduler;
+pub mod view;
+pub mod worker;
@@ -0,0 +1,283 @@
+//! 日志相关，根据 `[log]` 配置安装 tracing subscriber
+
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::watch;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation as AppenderRotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+
+use crate::config::{config_keys, Config};
+use crate::error::{Error, Result};
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Handle of the filter of the installed subscriber, see [`reload_filter`]
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Handle of the outputs of the installed subscriber, replaced by [`init`]
+static OUTPUT: OnceLock<reload::Handle<Vec<BoxedLayer>, Registry>> = OnceLock::new();
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogConfig {
+    /// Default log level
+    #[serde(default)]
+    pub level: LogLevel,
+
+    /// Extra filter directives, e.g. `sqlx=warn` or `tower_http=debug`.
+    /// Ignored when the `RUST_LOG` environment variable is set
+    #[serde(default)]
+    pub filters: Vec<String>,
+
+    /// Output format
+    #[serde(default)]
+    pub format: Format,
+
+    /// Enable ANSI colors on stdout
+    #[serde(default = "default_ansi")]
+    pub ansi: bool,
+
+    /// Also write logs to a rotating file
+    pub file: Option<FileConfig>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::default(),
+            filters: vec![],
+            format: Format::default(),
+            ansi: default_ansi(),
+            file: None,
+        }
+    }
+}
+
+fn default_ansi() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileConfig {
+    /// Directory of the log files
+    pub dir: PathBuf,
+
+    /// File name prefix, the rotation date is appended to it
+    #[serde(default = "default_file_prefix")]
+    pub prefix: String,
+
+    /// How often to roll over to a new file
+    #[serde(default)]
+    pub rotation: Rotation,
+
+    /// Output format of the file, defaults to `[log].format`
+    pub format: Option<Format>,
+}
+
+fn default_file_prefix() -> String {
+    "panshi".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Trace,
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl std::fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Trace => write!(f, "trace"),
+            Self::Debug => write!(f, "debug"),
+            Self::Info => write!(f, "info"),
+            Self::Warn => write!(f, "warn"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Compact,
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<Rotation> for AppenderRotation {
+    fn from(rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::Minutely => Self::MINUTELY,
+            Rotation::Hourly => Self::HOURLY,
+            Rotation::Daily => Self::DAILY,
+            Rotation::Never => Self::NEVER,
+        }
+    }
+}
+
+/// Keeps the background writer of the file appender alive, logs buffered
+/// in it are flushed when dropped.
+#[must_use = "logs written to file are lost when the guard is dropped"]
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+}
+
+impl LogConfig {
+    /// Read the `[log]` section, falling back to the defaults when absent.
+    ///
+    /// # Errors
+    /// Return an error when the section exists but is invalid.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(config
+            .get_optional::<Self>(config_keys::LOG)?
+            .unwrap_or_default())
+    }
+
+    pub(crate) fn env_filter(&self) -> Result<EnvFilter> {
+        if std::env::var(EnvFilter::DEFAULT_ENV).is_ok() {
+            return Ok(EnvFilter::from_default_env());
+        }
+
+        let mut directives = vec![self.level.to_string()];
+        directives.extend(self.filters.iter().cloned());
+
+        EnvFilter::try_new(directives.join(","))
+            .map_err(|err| Error::Message(format!("invalid log filter directives: {err}")))
+    }
+}
+
+fn fmt_layer<W>(format: Format, ansi: bool, writer: W) -> BoxedLayer
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_ansi(ansi)
+        .with_writer(writer);
+
+    match format {
+        Format::Compact => layer.compact().boxed(),
+        Format::Pretty => layer.pretty().boxed(),
+        Format::Json => layer.json().boxed(),
+    }
+}
+
+/// Install the global tracing subscriber with the default level and
+/// format, writing to stderr until [`init`] applies the `[log]` section.
+/// Logs the loading of the config and keeps stdout free for the output of
+/// the commands.
+///
+/// # Errors
+/// Return an error when `RUST_LOG` is invalid or a subscriber was already
+/// installed.
+pub fn init_default() -> Result<()> {
+    let config = LogConfig::default();
+    install(
+        vec![fmt_layer(config.format, config.ansi, std::io::stderr)],
+        config.env_filter()?,
+    )
+}
+
+/// Install the global tracing subscriber, or reconfigure the one installed
+/// by [`init_default`].
+///
+/// Must be called after daemonizing, the file appender writes from a
+/// background thread.
+///
+/// # Errors
+/// Return an error when the filter directives are invalid, the log directory
+/// could not be created or a subscriber not installed by this module was
+/// already installed.
+pub fn init(config: &LogConfig) -> Result<LogGuard> {
+    let mut layers: Vec<BoxedLayer> = vec![fmt_layer(config.format, config.ansi, std::io::stdout)];
+
+    let file_guard = if let Some(file) = &config.file {
+        let appender = RollingFileAppender::builder()
+            .rotation(file.rotation.into())
+            .filename_prefix(&file.prefix)
+            .filename_suffix("log")
+            .build(&file.dir)
+            .map_err(Error::wrap)?;
+        let (writer, guard) = tracing_appender::non_blocking(appender);
+        layers.push(fmt_layer(
+            file.format.unwrap_or(config.format),
+            false,
+            writer,
+        ));
+        Some(guard)
+    } else {
+        None
+    };
+
+    let filter = config.env_filter()?;
+    match (OUTPUT.get(), FILTER.get()) {
+        (Some(output), Some(handle)) => {
+            output
+                .reload(layers)
+                .and_then(|()| handle.reload(filter))
+                .map_err(|err| Error::Message(format!("failed to reconfigure the logs: {err}")))?;
+        }
+        _ => install(layers, filter)?,
+    }
+
+    Ok(LogGuard { _file: file_guard })
+}
+
+fn install(layers: Vec<BoxedLayer>, filter: EnvFilter) -> Result<()> {
+    let (layers, output) = reload::Layer::new(layers);
+    let (filter, handle) = reload::Layer::new(filter);
+    tracing_subscriber::registry()
+        .with(layers.with_filter(filter))
+        .try_init()
+        .map_err(Error::wrap)?;
+    let _ = OUTPUT.set(output);
+    let _ = FILTER.set(handle);
+    Ok(())
+}
+
+/// Replace the level and filters of the installed subscriber, the other
+/// options of `config` need a restart. Does nothing when no subscriber was
+/// installed by this module.
+///
+/// # Errors
+/// Return an error when the filter directives are invalid.
+pub fn reload_filter(config: &LogConfig) -> Result<()> {
+    let Some(handle) = FILTER.get() else {
+        return Ok(());
+    };
+    handle
+        .reload(config.env_filter()?)
+        .map_err(|err| Error::Message(format!("failed to reload the log filter: {err}")))
+}
+
+/// Apply the `[log]` section of each reloaded config, see
+/// [`crate::component::ComponentRegister::subscribe_config`].
+pub async fn follow(mut configs: watch::Receiver<Arc<Config>>) {
+    while configs.changed().await.is_ok() {
+        let config = configs.borrow_and_update().clone();
+        match LogConfig::from_config(&config).and_then(|log| reload_filter(&log)) {
+            Ok(()) => tracing::debug!("log filter reloaded"),
+            Err(err) => tracing::warn!(error = %err, "failed to reload the log filter"),
+        }
+    }
+}
@@ -0,0 +1,445 @@
+//! 定时任务：按 cron 表达式或固定间隔周期运行
+
+use chrono::{DateTime, Local, TimeZone};
+use futures::future::BoxFuture;
+use rand::Rng;
+use redis::Script;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+use crate::app::{create_app, watch_config, AppContext, AppTrait};
+use crate::component::lock::{DistributedLock, LockGuard};
+use crate::component::redis::AnyRedisPool;
+use crate::config::{config_keys, Config as AppConfig, Environment};
+use crate::error::{Error, Result};
+use crate::http::app::shutdown_signal;
+
+/// Ttl of the lock of a single node task, extended while the task runs
+const LOCK_TTL: Duration = Duration::from_secs(60);
+
+/// How long the last run marker of a single node task stays, on top of the
+/// jitter, to keep the late nodes from running the same instant again
+const LAST_RUN_TTL: Duration = Duration::from_secs(60);
+
+/// Record the scheduled instant unless it, or a later one, already ran
+static CLAIM_RUN: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        local last = tonumber(redis.call('GET', KEYS[1]))
+        if last and last >= tonumber(ARGV[1]) then
+            return 0
+        end
+        redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+        return 1
+        ",
+    )
+});
+
+/// The `[scheduler]` section
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Run the tasks in `start` too, not only in `panshi scheduler`
+    #[serde(default)]
+    pub run_with_server: bool,
+}
+
+impl Config {
+    /// Read the `[scheduler]` section, the defaults when it is missing.
+    ///
+    /// # Errors
+    /// Return an error when the section is invalid.
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        Ok(config
+            .get_optional(config_keys::SCHEDULER)?
+            .unwrap_or_default())
+    }
+}
+
+/// When a task runs
+#[derive(Clone)]
+pub enum Schedule {
+    Cron {
+        expression: String,
+        schedule: Box<cron::Schedule>,
+    },
+    /// Every interval since the Unix epoch, so every node runs the task at
+    /// the same instants
+    Interval(Duration),
+}
+
+impl Schedule {
+    /// Parse a cron expression, with or without the leading seconds field,
+    /// e.g. `*/5 * * * *` or `0 30 2 * * Mon-Fri`.
+    ///
+    /// # Errors
+    /// Return an error when the expression is invalid.
+    pub fn cron(expression: &str) -> Result<Self> {
+        let full = if expression.split_whitespace().count() == 5 {
+            format!("0 {expression}")
+        } else {
+            expression.to_string()
+        };
+
+        let schedule = cron::Schedule::from_str(&full).map_err(|err| {
+            Error::Message(format!("invalid cron expression `{expression}`: {err}"))
+        })?;
+        Ok(Self::Cron {
+            expression: expression.to_string(),
+            schedule: Box::new(schedule),
+        })
+    }
+
+    /// The first instant of the schedule strictly after `after`.
+    #[must_use]
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Self::Cron { schedule, .. } => schedule.after(&after).next(),
+            Self::Interval(interval) => {
+                let interval = i64::try_from(interval.as_millis()).ok()?.max(1);
+                let next = (after.timestamp_millis() / interval + 1).checked_mul(interval)?;
+                Local.timestamp_millis_opt(next).single()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cron { expression, .. } => f.write_str(expression),
+            Self::Interval(interval) => write!(f, "every {interval:?}"),
+        }
+    }
+}
+
+type Run<T> = Arc<dyn Fn(AppContext<T>) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// A periodic task, see [`Tasks`]
+pub struct Task<T: AppTrait> {
+    name: String,
+    schedule: Schedule,
+    single_node: bool,
+    jitter: Duration,
+    run: Run<T>,
+}
+
+impl<T: AppTrait> Task<T> {
+    /// Run the task on a single node per scheduled instant, the nodes sharing
+    /// the `AnyRedisPool` component race for a [`DistributedLock`].
+    pub fn single_node(&mut self) -> &mut Self {
+        self.single_node = true;
+        self
+    }
+
+    /// Delay each run by a random duration up to `jitter`, to spread the
+    /// tasks scheduled at the same instant.
+    pub fn jitter(&mut self, jitter: Duration) -> &mut Self {
+        self.jitter = jitter;
+        self
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+
+    #[must_use]
+    pub fn is_single_node(&self) -> bool {
+        self.single_node
+    }
+
+    #[must_use]
+    pub fn jitter_max(&self) -> Duration {
+        self.jitter
+    }
+
+    fn random_jitter(&self) -> Duration {
+        if self.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            rand::thread_rng().gen_range(Duration::ZERO..=self.jitter)
+        }
+    }
+}
+
+/// The periodic tasks of an application, registered by [`AppTrait::tasks`],
+/// e.g.
+///
+/// ```ignore
+/// tasks.cron("purge_sessions", "0 3 * * *", |ctx| async move { purge(&ctx).await })?;
+/// tasks
+///     .every("refresh_rates", Duration::from_secs(300), |ctx| async move { refresh(&ctx).await })
+///     .single_node()
+///     .jitter(Duration::from_secs(10));
+/// ```
+///
+/// A task does not overlap itself, the instants reached while it runs are
+/// skipped. Cron expressions are evaluated in the local time zone.
+pub struct Tasks<T: AppTrait> {
+    tasks: Vec<Task<T>>,
+}
+
+impl<T: AppTrait> Default for Tasks<T> {
+    fn default() -> Self {
+        Self { tasks: vec![] }
+    }
+}
+
+impl<T: AppTrait> Tasks<T> {
+    /// Run `f` on the instants of a cron expression, see [`Schedule::cron`].
+    ///
+    /// # Errors
+    /// Return an error when the expression is invalid.
+    pub fn cron<F, Fut>(&mut self, name: &str, expression: &str, f: F) -> Result<&mut Task<T>>
+    where
+        F: Fn(AppContext<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let schedule = Schedule::cron(expression)
+            .map_err(|err| Error::Message(format!("task `{name}`: {err}")))?;
+        Ok(self.add(name, schedule, f))
+    }
+
+    /// Run `f` every `interval`.
+    pub fn every<F, Fut>(&mut self, name: &str, interval: Duration, f: F) -> &mut Task<T>
+    where
+        F: Fn(AppContext<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.add(name, Schedule::Interval(interval), f)
+    }
+
+    fn add<F, Fut>(&mut self, name: &str, schedule: Schedule, f: F) -> &mut Task<T>
+    where
+        F: Fn(AppContext<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.tasks.push(Task {
+            name: name.to_string(),
+            schedule,
+            single_node: false,
+            jitter: Duration::ZERO,
+            run: Arc::new(move |ctx| Box::pin(f(ctx))),
+        });
+        self.tasks.last_mut().expect("task just pushed")
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Task<T>> {
+        self.tasks.iter()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+/// Collect the tasks of the application.
+///
+/// # Errors
+/// Return an error when a task is invalid.
+pub fn tasks<T: AppTrait>() -> Result<Tasks<T>> {
+    let mut tasks = Tasks::default();
+    T::tasks(&mut tasks)?;
+    Ok(tasks)
+}
+
+/// Boot the application and run its tasks until a shutdown signal is
+/// received, then wait for the running tasks and shut the components down.
+///
+/// # Errors
+/// Return an error when the application fails to initialize or a task is
+/// invalid.
+pub async fn start<T: AppTrait>(config: AppConfig, environment: Environment) -> Result<()> {
+    let tasks = tasks::<T>()?;
+    let ctx = create_app::<T>(config, environment).await?;
+    let config_watch = watch_config(&ctx)?;
+
+    let result = run(ctx.clone(), tasks, shutdown_signal()).await;
+    drop(config_watch);
+
+    // the running tasks are done, release the components they were using
+    ctx.components.shutdown().await;
+    tracing::info!("scheduler stopped");
+
+    result
+}
+
+/// Run `tasks` until `shutdown` completes, then wait for the running tasks.
+///
+/// # Errors
+/// Return an error when a single node task is registered and the
+/// `AnyRedisPool` component could not be created.
+pub async fn run<T: AppTrait>(
+    ctx: AppContext<T>,
+    tasks: Tasks<T>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let pool = if tasks.iter().any(Task::is_single_node) {
+        Some(ctx.component::<AnyRedisPool>().await?)
+    } else {
+        None
+    };
+
+    tracing::info!(
+        tasks = ?tasks.iter().map(Task::name).collect::<Vec<_>>(),
+        "scheduler started"
+    );
+
+    let (stop, stopped) = watch::channel(false);
+    let mut running = JoinSet::new();
+    for task in tasks.tasks {
+        running.spawn(run_task(ctx.clone(), task, pool.clone(), stopped.clone()));
+    }
+
+    shutdown.await;
+    tracing::info!("scheduler stopping, waiting for the running tasks");
+    let _ = stop.send(true);
+    while running.join_next().await.is_some() {}
+
+    Ok(())
+}
+
+async fn run_task<T: AppTrait>(
+    ctx: AppContext<T>,
+    task: Task<T>,
+    pool: Option<AnyRedisPool>,
+    mut stopped: watch::Receiver<bool>,
+) {
+    loop {
+        let Some(next) = task.schedule.next_after(Local::now()) else {
+            tracing::info!(task = task.name, "task has no upcoming run");
+            return;
+        };
+
+        let jitter = task.random_jitter();
+        let wait = (next - Local::now()).to_std().unwrap_or_default() + jitter;
+        tokio::select! {
+            () = tokio::time::sleep(wait) => {}
+            _ = stopped.changed() => return,
+        }
+
+        let guard = match (&pool, task.single_node) {
+            (Some(pool), true) => match claim_run(&ctx, &task, pool, next).await {
+                Ok(Some(guard)) => Some(guard),
+                Ok(None) => {
+                    tracing::debug!(task = task.name, "task run by another node");
+                    continue;
+                }
+                Err(err) => {
+                    tracing::warn!(task = task.name, error = %err, "failed to lock task run");
+                    continue;
+                }
+            },
+            _ => None,
+        };
+
+        tracing::debug!(task = task.name, scheduled = %next, ?jitter, "task started");
+        let started = Instant::now();
+        // a panicking task fails like a task returning an error
+        let result = tokio::spawn((task.run)(ctx.clone()))
+            .await
+            .unwrap_or_else(|err| Err(Error::Message(format!("task panicked: {err}"))));
+        match result {
+            Ok(()) => tracing::debug!(
+                task = task.name,
+                elapsed_ms = started.elapsed().as_millis(),
+                "task done"
+            ),
+            Err(err) => tracing::error!(task = task.name, error = %err, "task failed"),
+        }
+
+        if let Some(guard) = guard {
+            if let Err(err) = guard.release().await {
+                tracing::warn!(task = task.name, error = %err, "failed to unlock task");
+            }
+        }
+    }
+}
+
+/// Take the lock of the task, held while it runs, and claim the run of
+/// `next`. Both keys are the same for every run, the lock is released after
+/// the run and the last run marker expires.
+async fn claim_run<T: AppTrait>(
+    ctx: &AppContext<T>,
+    task: &Task<T>,
+    pool: &AnyRedisPool,
+    next: DateTime<Local>,
+) -> Result<Option<LockGuard>> {
+    let name = format!(
+        "{}:scheduler:{}",
+        ctx.components.app_name().unwrap_or_default(),
+        task.name
+    );
+    let lock = DistributedLock::new(pool.clone(), &name, LOCK_TTL);
+    let Some(guard) = lock.try_acquire().await? else {
+        return Ok(None);
+    };
+
+    let ttl = LAST_RUN_TTL + task.jitter;
+    let claimed: u64 = CLAIM_RUN
+        .key(format!("{name}:last_run"))
+        .arg(next.timestamp_millis())
+        .arg(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX))
+        .invoke_async(&mut *pool.acquire().await?)
+        .await?;
+
+    if claimed == 0 {
+        guard.release().await?;
+        return Ok(None);
+    }
+    Ok(Some(guard))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+
+    fn at(hour: u32, min: u32, sec: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, 15, hour, min, sec).unwrap()
+    }
+
+    #[test]
+    fn cron_without_seconds() {
+        let schedule = Schedule::cron("*/5 * * * *").unwrap();
+        assert_eq!(schedule.to_string(), "*/5 * * * *");
+        assert_eq!(schedule.next_after(at(10, 2, 30)), Some(at(10, 5, 0)));
+        assert_eq!(schedule.next_after(at(10, 5, 0)), Some(at(10, 10, 0)));
+    }
+
+    #[test]
+    fn cron_with_seconds() {
+        let schedule = Schedule::cron("30 0 12 * * Mon-Fri").unwrap();
+        // 2024-01-15 is a Monday
+        assert_eq!(schedule.next_after(at(10, 0, 0)), Some(at(12, 0, 30)));
+    }
+
+    #[test]
+    fn invalid_cron_is_an_error() {
+        assert!(Schedule::cron("* * *").is_err());
+        assert!(Schedule::cron("61 * * * *").is_err());
+    }
+
+    #[test]
+    fn interval_is_aligned_on_the_epoch() {
+        let schedule = Schedule::Interval(Duration::from_secs(60));
+        let next = schedule.next_after(at(10, 2, 30)).unwrap();
+        assert_eq!((next.minute(), next.second()), (3, 0));
+        assert_eq!(next.timestamp() % 60, 0);
+
+        let next_again = schedule.next_after(next).unwrap();
+        assert_eq!(next_again - next, chrono::Duration::seconds(60));
+    }
+}
@@ -0,0 +1,404 @@
+//! 后台任务：任务定义、入队以及运行任务的 worker
+
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+use crate::app::{create_app, watch_config, AppContext, AppTrait};
+use crate::component::ComponentProvider;
+use crate::config::{Config as AppConfig, Environment};
+use crate::error::{Error, Result};
+use crate::http::app::shutdown_signal;
+
+pub mod queue;
+
+use queue::{Fetched, WORKER_TTL};
+pub use queue::{JobEnvelope, JobQueue};
+
+/// Queue of the jobs not choosing one
+pub const DEFAULT_QUEUE: &str = "default";
+
+/// Due scheduled jobs moved to their queue per round trip
+const PROMOTE_BATCH: usize = 100;
+
+/// The `[worker]` section, durations are in milliseconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// Number of jobs run at the same time by `panshi worker`
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+
+    /// Queues fetched by `panshi worker`, in priority order
+    #[serde(default = "default_queues")]
+    pub queues: Vec<String>,
+
+    /// Wait before fetching again when the queues are empty
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+
+    /// Delay of the first retry, doubled on each following one
+    #[serde(default = "default_backoff_min")]
+    pub backoff_min: u64,
+
+    /// Longest delay between two retries
+    #[serde(default = "default_backoff_max")]
+    pub backoff_max: u64,
+
+    /// Jobs kept in the dead-letter queue of each queue, the oldest are
+    /// dropped first
+    #[serde(default = "default_dead_limit")]
+    pub dead_limit: usize,
+
+    /// Prefix of the keys, defaults to the application name. Set it to an
+    /// empty string to disable the namespacing
+    pub namespace: Option<String>,
+
+    /// Instance of the `AnyRedisPool` component holding the queues, the
+    /// default instance when not set
+    pub redis: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            concurrency: default_concurrency(),
+            queues: default_queues(),
+            poll_interval: default_poll_interval(),
+            backoff_min: default_backoff_min(),
+            backoff_max: default_backoff_max(),
+            dead_limit: default_dead_limit(),
+            namespace: None,
+            redis: None,
+        }
+    }
+}
+
+fn default_concurrency() -> usize {
+    4
+}
+
+fn default_queues() -> Vec<String> {
+    vec![DEFAULT_QUEUE.to_string()]
+}
+
+fn default_poll_interval() -> u64 {
+    1000
+}
+
+fn default_backoff_min() -> u64 {
+    1000
+}
+
+fn default_backoff_max() -> u64 {
+    3_600_000
+}
+
+fn default_dead_limit() -> usize {
+    10_000
+}
+
+/// A job run in the background by `panshi worker`, its fields are the
+/// payload stored in the queue, e.g.
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct SendWelcome {
+///     user_id: i64,
+/// }
+///
+/// #[async_trait::async_trait]
+/// impl Job<App> for SendWelcome {
+///     const NAME: &'static str = "send_welcome";
+///
+///     async fn perform(self, ctx: AppContext<App>) -> Result<()> {
+///         let mailer = ctx.component::<Mailer>().await?;
+///         mailer.welcome(self.user_id).await
+///     }
+/// }
+///
+/// queue.enqueue(&SendWelcome { user_id: 1 }).await?;
+/// ```
+///
+/// Jobs must be registered by [`AppTrait::jobs`] to be run. A failed job is
+/// retried with an exponential backoff, then moved to the dead-letter queue.
+#[async_trait::async_trait]
+pub trait Job<T: AppTrait>: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Unique name of the job, stored with its payload
+    const NAME: &'static str;
+
+    /// Queue the job is pushed to
+    fn queue() -> &'static str {
+        DEFAULT_QUEUE
+    }
+
+    /// Retries after the first failure before the job is dead
+    fn max_retries() -> u32 {
+        5
+    }
+
+    /// Run the job.
+    ///
+    /// # Errors
+    /// A returned error retries the job.
+    async fn perform(self, ctx: AppContext<T>) -> Result<()>;
+}
+
+type Perform<T> =
+    Arc<dyn Fn(serde_json::Value, AppContext<T>) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// The jobs a worker knows how to run, by name
+pub struct Jobs<T: AppTrait> {
+    jobs: HashMap<&'static str, Perform<T>>,
+}
+
+impl<T: AppTrait> Default for Jobs<T> {
+    fn default() -> Self {
+        Self {
+            jobs: HashMap::new(),
+        }
+    }
+}
+
+impl<T: AppTrait> Jobs<T> {
+    /// Register the job `J`, replacing a job of the same name.
+    pub fn register<J: Job<T>>(&mut self) -> &mut Self {
+        let perform: Perform<T> = Arc::new(|args, ctx| {
+            Box::pin(async move {
+                let job: J = serde_json::from_value(args).map_err(Error::wrap)?;
+                job.perform(ctx).await
+            })
+        });
+        self.jobs.insert(J::NAME, perform);
+        self
+    }
+}
+
+/// Boot the application and run its jobs until a shutdown signal is
+/// received, then wait for the running jobs and shut the components down.
+///
+/// `concurrency` and `queues` override the `[worker]` section when given.
+///
+/// # Errors
+/// Return an error when the config is invalid or the application fails to
+/// initialize.
+pub async fn start<T: AppTrait>(
+    config: AppConfig,
+    environment: Environment,
+    concurrency: Option<usize>,
+    queues: Vec<String>,
+) -> Result<()> {
+    let mut worker_config: Config = config
+        .get_optional(JobQueue::config_key())?
+        .unwrap_or_default();
+    if let Some(concurrency) = concurrency {
+        worker_config.concurrency = concurrency;
+    }
+    if !queues.is_empty() {
+        worker_config.queues = queues;
+    }
+
+    let ctx = create_app::<T>(config, environment).await?;
+    let config_watch = watch_config(&ctx)?;
+    let queue = ctx.component::<JobQueue>().await?;
+    let mut jobs = Jobs::default();
+    T::jobs(&mut jobs);
+
+    tracing::info!(
+        concurrency = worker_config.concurrency,
+        queues = ?worker_config.queues,
+        jobs = ?jobs.jobs.keys().collect::<Vec<_>>(),
+        "worker started"
+    );
+    run(ctx.clone(), jobs, queue, &worker_config, shutdown_signal()).await;
+    drop(config_watch);
+
+    // the running jobs are done, release the components they were using
+    ctx.components.shutdown().await;
+    tracing::info!("worker stopped");
+
+    Ok(())
+}
+
+/// Run the jobs of `queues` until `shutdown` completes, then wait for the
+/// running jobs.
+pub async fn run<T: AppTrait>(
+    ctx: AppContext<T>,
+    jobs: Jobs<T>,
+    queue: JobQueue,
+    config: &Config,
+    shutdown: impl Future<Output = ()>,
+) {
+    let jobs = Arc::new(jobs);
+    let queues = Arc::new(config.queues.clone());
+    let worker: Arc<str> = uuid::Uuid::new_v4().to_string().into();
+    let poll_interval = Duration::from_millis(config.poll_interval.max(1));
+    let (stop, stopped) = watch::channel(false);
+
+    // alive before recovering, the jobs of this worker are never recovered
+    if let Err(err) = queue.heartbeat(&queues, &worker).await {
+        tracing::warn!(error = %err, "failed to register the worker");
+    }
+    recover(&queue, &queues).await;
+
+    let mut tasks = JoinSet::new();
+    for _ in 0..config.concurrency.max(1) {
+        tasks.spawn(fetch(
+            ctx.clone(),
+            jobs.clone(),
+            queue.clone(),
+            queues.clone(),
+            worker.clone(),
+            poll_interval,
+            stopped.clone(),
+        ));
+    }
+    tasks.spawn(promote(
+        queue.clone(),
+        queues.clone(),
+        poll_interval,
+        stopped.clone(),
+    ));
+    tasks.spawn(heartbeat(
+        queue.clone(),
+        queues.clone(),
+        worker.clone(),
+        stopped,
+    ));
+
+    shutdown.await;
+    tracing::info!("worker stopping, waiting for the running jobs");
+    let _ = stop.send(true);
+    while tasks.join_next().await.is_some() {}
+
+    if let Err(err) = queue.unregister(&queues, &worker).await {
+        tracing::warn!(error = %err, "failed to unregister the worker");
+    }
+}
+
+/// Fetch and run jobs one at a time.
+async fn fetch<T: AppTrait>(
+    ctx: AppContext<T>,
+    jobs: Arc<Jobs<T>>,
+    queue: JobQueue,
+    queues: Arc<Vec<String>>,
+    worker: Arc<str>,
+    poll_interval: Duration,
+    mut stopped: watch::Receiver<bool>,
+) {
+    while !*stopped.borrow() {
+        match queue.dequeue(&queues, &worker).await {
+            Ok(Some(fetched)) => perform(&ctx, &jobs, &queue, fetched).await,
+            Ok(None) => wait(poll_interval, &mut stopped).await,
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to fetch jobs");
+                wait(poll_interval, &mut stopped).await;
+            }
+        }
+    }
+}
+
+/// Keep the worker alive and give the jobs of the dead workers back to
+/// their queue.
+async fn heartbeat(
+    queue: JobQueue,
+    queues: Arc<Vec<String>>,
+    worker: Arc<str>,
+    mut stopped: watch::Receiver<bool>,
+) {
+    loop {
+        wait(WORKER_TTL / 3, &mut stopped).await;
+        if *stopped.borrow() {
+            return;
+        }
+
+        if let Err(err) = queue.heartbeat(&queues, &worker).await {
+            tracing::warn!(error = %err, "failed to renew the worker heartbeat");
+        }
+        recover(&queue, &queues).await;
+    }
+}
+
+async fn recover(queue: &JobQueue, queues: &[String]) {
+    for name in queues {
+        if let Err(err) = queue.recover(name).await {
+            tracing::warn!(queue = name, error = %err, "failed to recover jobs");
+        }
+    }
+}
+
+/// Move the due scheduled and retried jobs to their queue.
+async fn promote(
+    queue: JobQueue,
+    queues: Arc<Vec<String>>,
+    poll_interval: Duration,
+    mut stopped: watch::Receiver<bool>,
+) {
+    while !*stopped.borrow() {
+        for name in queues.iter() {
+            loop {
+                match queue.promote(name, PROMOTE_BATCH).await {
+                    Ok(moved) if moved == PROMOTE_BATCH => {}
+                    Ok(_) => break,
+                    Err(err) => {
+                        tracing::warn!(queue = name, error = %err, "failed to promote jobs");
+                        break;
+                    }
+                }
+            }
+        }
+        wait(poll_interval, &mut stopped).await;
+    }
+}
+
+async fn wait(duration: Duration, stopped: &mut watch::Receiver<bool>) {
+    tokio::select! {
+        () = tokio::time::sleep(duration) => {}
+        _ = stopped.changed() => {}
+    }
+}
+
+async fn perform<T: AppTrait>(
+    ctx: &AppContext<T>,
+    jobs: &Jobs<T>,
+    queue: &JobQueue,
+    fetched: Fetched,
+) {
+    let envelope = &fetched.envelope;
+    let result = match jobs.jobs.get(envelope.name.as_str()) {
+        // a panicking job fails like a job returning an error
+        Some(perform) => tokio::spawn(perform(envelope.args.clone(), ctx.clone()))
+            .await
+            .unwrap_or_else(|err| Err(Error::Message(format!("job panicked: {err}")))),
+        None => Err(Error::Message(format!(
+            "job `{}` is not registered",
+            envelope.name
+        ))),
+    };
+
+    match result {
+        Ok(()) => {
+            tracing::debug!(job = envelope.name, id = envelope.id, "job done");
+            if let Err(err) = queue.done(&fetched).await {
+                tracing::error!(error = %err, "failed to record the job success, the job may run again");
+            }
+        }
+        Err(err) => {
+            tracing::warn!(
+                job = envelope.name,
+                id = envelope.id,
+                attempts = envelope.attempts + 1,
+                error = %err,
+                "job failed"
+            );
+            if let Err(err) = queue.fail(fetched, &err.to_string()).await {
+                tracing::error!(error = %err, "failed to record the job failure, the job may run again");
+            }
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//! 基于 Redis 的任务队列，每个队列由待执行列表、延时有序集合、死信列表以及
+//! 各 worker 的执行中列表组成
+
+use redis::{AsyncCommands, Direction, Script};
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{Config, Job};
+use crate::app::AppTrait;
+use crate::component::redis::{AnyConnection, AnyRedisPool};
+use crate::component::{ComponentProvider, ComponentRegister};
+use crate::error::{Error, Result};
+
+/// Move up to `ARGV[2]` jobs due at `ARGV[1]` from the scheduled set to the
+/// queue
+static PROMOTE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        local due = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, ARGV[2])
+        for _, job in ipairs(due) do
+            redis.call('ZREM', KEYS[1], job)
+            redis.call('LPUSH', KEYS[2], job)
+        end
+        return #due
+        ",
+    )
+});
+
+/// Give the jobs being run by the worker `ARGV[1]` back to the queue, unless
+/// its heartbeat is alive. The oldest job ends up the next one fetched
+static RECOVER: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('EXISTS', KEYS[1]) == 1 then
+            return 0
+        end
+        local count = 0
+        while redis.call('LMOVE', KEYS[2], KEYS[3], 'LEFT', 'RIGHT') do
+            count = count + 1
+        end
+        redis.call('SREM', KEYS[4], ARGV[1])
+        return count
+        ",
+    )
+});
+
+/// How long a worker is alive after its last heartbeat, its running jobs are
+/// then given back to their queue
+pub(crate) const WORKER_TTL: Duration = Duration::from_secs(60);
+
+/// A job as stored in the queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobEnvelope {
+    pub id: String,
+    /// [`Job::NAME`]
+    pub name: String,
+    pub queue: String,
+    /// The serialized job
+    pub args: serde_json::Value,
+    /// Failed runs so far
+    pub attempts: u32,
+    pub max_retries: u32,
+    /// Unix time in milliseconds
+    pub enqueued_at: u64,
+    /// Error of the last failed run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A job fetched by a worker, kept in the processing list of the worker until
+/// it is done or failed
+pub(crate) struct Fetched {
+    pub envelope: JobEnvelope,
+    /// The job as stored, to remove it from the processing list
+    raw: String,
+    processing: String,
+}
+
+/// Push jobs to the queues of Redis and fetch them for the workers.
+///
+/// A fetched job is moved to the processing list of its worker. The jobs of a
+/// worker killed while running them are given back to their queue once its
+/// heartbeat expires, by the next worker starting or heart beating, so a job
+/// may run more than once. Stop workers with a shutdown signal to let the
+/// running jobs finish.
+#[derive(Clone)]
+pub struct JobQueue {
+    pool: AnyRedisPool,
+    namespace: String,
+    backoff_min: Duration,
+    backoff_max: Duration,
+    dead_limit: usize,
+}
+
+impl JobQueue {
+    fn key(&self, queue: &str, suffix: &str) -> String {
+        // the keys of a queue share a hash tag to live in the same slot of a
+        // cluster
+        if self.namespace.is_empty() {
+            format!("queue:{{{queue}}}{suffix}")
+        } else {
+            format!("{}:queue:{{{queue}}}{suffix}", self.namespace)
+        }
+    }
+
+    /// Push `job` to its queue, return the job id.
+    ///
+    /// # Errors
+    /// Return an error when the job could not be serialized or Redis fails.
+    pub async fn enqueue<T, J>(&self, job: &J) -> Result<String>
+    where
+        T: AppTrait,
+        J: Job<T>,
+    {
+        self.push::<T, J>(job, None).await
+    }
+
+    /// Push `job` to its queue once `at` is reached, return the job id.
+    ///
+    /// # Errors
+    /// Same as [`JobQueue::enqueue`].
+    pub async fn enqueue_at<T, J>(&self, job: &J, at: SystemTime) -> Result<String>
+    where
+        T: AppTrait,
+        J: Job<T>,
+    {
+        self.push::<T, J>(job, Some(unix_millis(at))).await
+    }
+
+    /// Push `job` to its queue after `delay`, return the job id.
+    ///
+    /// # Errors
+    /// Same as [`JobQueue::enqueue`].
+    pub async fn enqueue_in<T, J>(&self, job: &J, delay: Duration) -> Result<String>
+    where
+        T: AppTrait,
+        J: Job<T>,
+    {
+        self.enqueue_at::<T, J>(job, SystemTime::now() + delay)
+            .await
+    }
+
+    async fn push<T, J>(&self, job: &J, at: Option<u64>) -> Result<String>
+    where
+        T: AppTrait,
+        J: Job<T>,
+    {
+        let envelope = JobEnvelope {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: J::NAME.to_string(),
+            queue: J::queue().to_string(),
+            args: serde_json::to_value(job).map_err(Error::wrap)?,
+            attempts: 0,
+            max_retries: J::max_retries(),
+            enqueued_at: unix_millis(SystemTime::now()),
+            error: None,
+        };
+        let value = serde_json::to_string(&envelope).map_err(Error::wrap)?;
+
+        let mut conn = self.pool.acquire().await?;
+        match at {
+            Some(at) => {
+                conn.zadd::<_, _, _, ()>(self.key(&envelope.queue, ":scheduled"), value, at)
+                    .await?;
+            }
+            None => {
+                conn.lpush::<_, _, ()>(self.key(&envelope.queue, ""), value)
+                    .await?
+            }
+        }
+
+        tracing::debug!(
+            job = envelope.name,
+            id = envelope.id,
+            queue = envelope.queue,
+            "job enqueued"
+        );
+        Ok(envelope.id)
+    }
+
+    /// Move the oldest job of the first non-empty queue to the processing
+    /// list of `worker`. A job which could not be deserialized is moved to
+    /// the dead-letter queue.
+    pub(crate) async fn dequeue(&self, queues: &[String], worker: &str) -> Result<Option<Fetched>> {
+        let mut conn = self.pool.acquire().await?;
+        for queue in queues {
+            let processing = self.key(queue, &format!(":processing:{worker}"));
+            loop {
+                let raw: Option<String> = conn
+                    .lmove(
+                        self.key(queue, ""),
+                        &processing,
+                        Direction::Right,
+                        Direction::Left,
+                    )
+                    .await?;
+                let Some(raw) = raw else {
+                    break;
+                };
+
+                match serde_json::from_str(&raw) {
+                    Ok(envelope) => {
+                        return Ok(Some(Fetched {
+                            envelope,
+                            raw,
+                            processing,
+                        }))
+                    }
+                    Err(err) => {
+                        tracing::warn!(queue, error = %err, "malformed job moved to the dead-letter queue");
+                        let envelope = JobEnvelope {
+                            id: uuid::Uuid::new_v4().to_string(),
+                            name: String::new(),
+                            queue: queue.clone(),
+                            args: serde_json::Value::String(raw.clone()),
+                            attempts: 0,
+                            max_retries: 0,
+                            enqueued_at: unix_millis(SystemTime::now()),
+                            error: Some(format!("malformed job: {err}")),
+                        };
+                        let fetched = Fetched {
+                            envelope,
+                            raw,
+                            processing: processing.clone(),
+                        };
+                        self.bury(&mut conn, &fetched).await?;
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remove a job run successfully from the processing list.
+    pub(crate) async fn done(&self, fetched: &Fetched) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        conn.lrem::<_, _, ()>(&fetched.processing, 1, &fetched.raw)
+            .await?;
+        Ok(())
+    }
+
+    /// Move up to `count` due jobs of `queue` from the scheduled set to the
+    /// queue, return the number of moved jobs.
+    pub(crate) async fn promote(&self, queue: &str, count: usize) -> Result<usize> {
+        let mut conn = self.pool.acquire().await?;
+        Ok(PROMOTE
+            .key(self.key(queue, ":scheduled"))
+            .key(self.key(queue, ""))
+            .arg(unix_millis(SystemTime::now()))
+            .arg(count)
+            .invoke_async(&mut *conn)
+            .await?)
+    }
+
+    /// Schedule the retry of a failed job, or move it to the dead-letter
+    /// queue when it has no retry left.
+    pub(crate) async fn fail(&self, mut fetched: Fetched, error: &str) -> Result<()> {
+        fetched.envelope.attempts += 1;
+        fetched.envelope.error = Some(error.to_string());
+
+        let mut conn = self.pool.acquire().await?;
+        if fetched.envelope.attempts > fetched.envelope.max_retries {
+            tracing::warn!(
+                job = fetched.envelope.name,
+                id = fetched.envelope.id,
+                "job is dead"
+            );
+            self.bury(&mut conn, &fetched).await?;
+        } else {
+            let value = serde_json::to_string(&fetched.envelope).map_err(Error::wrap)?;
+            let at = SystemTime::now() + self.backoff(fetched.envelope.attempts);
+            redis::pipe()
+                .atomic()
+                .zadd(
+                    self.key(&fetched.envelope.queue, ":scheduled"),
+                    value,
+                    unix_millis(at),
+                )
+                .lrem(&fetched.processing, 1, &fetched.raw)
+                .query_async::<()>(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Move a job from the processing list to the dead-letter queue.
+    async fn bury(&self, conn: &mut AnyConnection, fetched: &Fetched) -> Result<()> {
+        let value = serde_json::to_string(&fetched.envelope).map_err(Error::wrap)?;
+        let dead = self.key(&fetched.envelope.queue, ":dead");
+        redis::pipe()
+            .atomic()
+            .lpush(&dead, value)
+            .ltrim(
+                &dead,
+                0,
+                isize::try_from(self.dead_limit).unwrap_or(isize::MAX) - 1,
+            )
+            .lrem(&fetched.processing, 1, &fetched.raw)
+            .query_async::<()>(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Mark `worker` alive for [`WORKER_TTL`] on `queues`.
+    pub(crate) async fn heartbeat(&self, queues: &[String], worker: &str) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        for queue in queues {
+            redis::pipe()
+                .sadd(self.key(queue, ":workers"), worker)
+                .pset_ex(
+                    self.key(queue, &format!(":worker:{worker}")),
+                    1,
+                    u64::try_from(WORKER_TTL.as_millis()).unwrap_or(u64::MAX),
+                )
+                .query_async::<()>(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Remove `worker` from `queues` once it stopped running jobs.
+    pub(crate) async fn unregister(&self, queues: &[String], worker: &str) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        for queue in queues {
+            redis::pipe()
+                .srem(self.key(queue, ":workers"), worker)
+                .del(self.key(queue, &format!(":worker:{worker}")))
+                .query_async::<()>(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Give the jobs of the dead workers of `queue` back to it, return the
+    /// number of recovered jobs.
+    pub(crate) async fn recover(&self, queue: &str) -> Result<usize> {
+        let mut conn = self.pool.acquire().await?;
+        let workers: Vec<String> = conn.smembers(self.key(queue, ":workers")).await?;
+
+        let mut recovered = 0;
+        for worker in workers {
+            let count: usize = RECOVER
+                .key(self.key(queue, &format!(":worker:{worker}")))
+                .key(self.key(queue, &format!(":processing:{worker}")))
+                .key(self.key(queue, ""))
+                .key(self.key(queue, ":workers"))
+                .arg(&worker)
+                .invoke_async(&mut *conn)
+                .await?;
+            if count > 0 {
+                tracing::warn!(
+                    queue,
+                    worker,
+                    count,
+                    "jobs of a dead worker given back to the queue"
+                );
+            }
+            recovered += count;
+        }
+        Ok(recovered)
+    }
+
+    /// Delay before the retry following the failed run `attempts`.
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.backoff_min
+            .saturating_mul(factor)
+            .min(self.backoff_max)
+    }
+
+    /// The newest `count` dead jobs of `queue`.
+    ///
+    /// # Errors
+    /// Return an error when Redis fails or a job could not be deserialized.
+    pub async fn dead_jobs(&self, queue: &str, count: usize) -> Result<Vec<JobEnvelope>> {
+        if count == 0 {
+            return Ok(vec![]);
+        }
+
+        let mut conn = self.pool.acquire().await?;
+        let stop = isize::try_from(count).unwrap_or(isize::MAX) - 1;
+        let values: Vec<String> = conn.lrange(self.key(queue, ":dead"), 0, stop).await?;
+        values
+            .iter()
+            .map(|value| serde_json::from_str(value).map_err(Error::wrap))
+            .collect()
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+}
+
+#[async_trait::async_trait]
+impl ComponentProvider for JobQueue {
+    type Error = Error;
+
+    type Config = Config;
+
+    fn config_key() -> &'static str {
+        "worker"
+    }
+
+    async fn create(config: Self::Config, component_register: &ComponentRegister) -> Result<Self> {
+        let namespace = config
+            .namespace
+            .or_else(|| component_register.app_name().map(ToString::to_string))
+            .unwrap_or_default();
+
+        let pool = match &config.redis {
+            Some(name) => {
+                component_register
+                    .component_named::<AnyRedisPool>(name)
+                    .await?
+            }
+            None => component_register.component::<AnyRedisPool>().await?,
+        };
+
+        Ok(Self {
+            pool,
+            namespace,
+            backoff_min: Duration::from_millis(config.backoff_min),
+            backoff_max: Duration::from_millis(config.backoff_max),
+            dead_limit: config.dead_limit.max(1),
+        })
+    }
+}